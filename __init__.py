@@ -1,7 +1,7 @@
 import bpy
 import json
 import mathutils
-from math import radians, atan, tan
+from math import radians, asin
 from bpy_extras.io_utils import axis_conversion
 import os
 import platform
@@ -26,6 +26,56 @@ bl_info = {
     "category": "Texturing"}
 
 
+def sequence_pattern(path):
+    # shot_0012.jpg -> shot_####.jpg, eyek puts the frame number in place of the #
+    head, ext = os.path.splitext(path)
+    digits = len(head) - len(head.rstrip("0123456789"))
+    if digits == 0:
+        return path
+    return head[:-digits] + "#" * digits + ext
+
+
+def panorama_setting(cam_data, name):
+    # Panorama settings moved from Cycles to the camera in Blender 4.0
+    if hasattr(cam_data, name):
+        return getattr(cam_data, name)
+    return getattr(cam_data.cycles, name)
+
+
+def panorama_camera(cam_data):
+    # eyek camera type and fov of a PANO camera, None for unsupported panoramas
+    panorama_type = panorama_setting(cam_data, "panorama_type")
+    if panorama_type == 'EQUIRECTANGULAR':
+        return "equirectangular", None
+    if panorama_type == 'FISHEYE_EQUIDISTANT':
+        return "fisheye_equidistant", panorama_setting(cam_data, "fisheye_fov")
+    if panorama_type == 'FISHEYE_EQUISOLID':
+        # Sensor of the fit dimension is covered by r = 2 f sin(theta / 2)
+        sensor = cam_data.sensor_height if cam_data.sensor_fit == 'VERTICAL' else cam_data.sensor_width
+        lens = panorama_setting(cam_data, "fisheye_lens")
+        return "fisheye_equisolid", 4 * asin(min(sensor / (4 * lens), 1.0))
+    return None, None
+
+
+def sequence_keys(cam, image_user, global_matrix):
+    # Camera pose at every scene frame of the image sequence, keyed by image frame
+    scene = bpy.context.scene
+    frame_current = scene.frame_current
+    keys = []
+    for frame in range(image_user.frame_start, image_user.frame_start + image_user.frame_duration):
+        scene.frame_set(frame)
+        cam_matrix = global_matrix @ cam.matrix_world
+        l_x, l_y, l_z = cam_matrix.to_translation()
+        re_x, re_y, re_z = cam_matrix.to_euler()
+        keys.append({
+                    "frame": frame - image_user.frame_start + 1 + image_user.frame_offset,
+                    "location": {"x": l_x, "y": l_y, "z": l_z},
+                    "rotation_euler": {"x": re_x, "y": re_y, "z": re_z},
+                    })
+    scene.frame_set(frame_current)
+    return keys
+
+
 class EYEK_Properties(bpy.types.PropertyGroup):
     res_x: bpy.props.IntProperty(default=512, min=2, subtype='PIXEL', description="Number of horizontal pixels in the generated texture.")
     res_y: bpy.props.IntProperty(default=512, min=2, subtype='PIXEL',description="Number of vertical pixels in the generated texture.")
@@ -84,12 +134,13 @@ class EYEK_exe(bpy.types.Operator):
             except:
                 pass
             os.mkdir(eyek_dir)
+            open(os.path.join(eyek_dir, ".eyek"), 'w').close()
             
             cameras_data = []
             cameras.sort(key=lambda x: x.name)
 
             render = bpy.context.scene.render
-            render_ratio = render.resolution_x / render.resolution_y
+            pixel_aspect = render.pixel_aspect_x / render.pixel_aspect_y
             for cam in cameras:
                 cam_matrix = global_matrix @ cam.matrix_world
                 l_x, l_y, l_z = cam_matrix.to_translation()
@@ -97,26 +148,21 @@ class EYEK_exe(bpy.types.Operator):
                 sc_x, sc_y, sc_z = cam_matrix.to_scale()
                 if cam.type == 'CAMERA':
                     cam_image = bpy.data.images[cam.data.background_images[0].image.name]
-                    img_ratio = cam_image.size[0] / cam_image.size[1]
-
-                    fov = cam.data.angle
                     cam.data.background_images[0].frame_method = 'CROP'
-                    if render_ratio >= 1.0:
-                        if cam.data.type == 'ORTHO':
-                            fov = -cam.data.ortho_scale
-                            sc_x *= -fov
-                            sc_y *= -fov / img_ratio
-
-                    else:
-                        if cam.data.type == 'ORTHO':
-                            fov = -cam.data.ortho_scale
-                            sc_x *= -fov * img_ratio
-                            sc_y *= -fov
-                        else:
-                            fov = 2 * atan(tan(fov / 2) * img_ratio)
 
-
-                    sc_z *= -fov
+                    # Angle and ortho scale span the sensor fit dimension, eyek resolves the fit
+                    fov = cam.data.angle
+                    camera_type = None
+                    if cam.data.type == 'PANO':
+                        camera_type, fov = panorama_camera(cam.data)
+                        if camera_type is None:
+                            self.report({'WARNING'}, cam.name + ": unsupported panorama type, skipped.")
+                            continue
+                    if cam.data.type == 'ORTHO':
+                        fov = -cam.data.ortho_scale
+                        sc_x *= -fov
+                        sc_y *= -fov
+                        sc_z *= -fov
                     cam_near = cam.data.clip_start
                     cam_far = cam.data.clip_end
                 
@@ -146,6 +192,19 @@ class EYEK_exe(bpy.types.Operator):
                             "limit_far": cam_far, 
                             "image_path": image_path,
                             }
+                if cam.type == 'CAMERA':
+                    if camera_type is not None:
+                        cam_data["camera_type"] = camera_type
+                        if fov is None:
+                            del cam_data["fov_x"]
+                    cam_data["shift_x"] = cam.data.shift_x
+                    cam_data["shift_y"] = cam.data.shift_y
+                    cam_data["sensor_fit"] = cam.data.sensor_fit.lower()
+                    cam_data["pixel_aspect"] = pixel_aspect
+                    background = cam.data.background_images[0]
+                    if background.image.source == 'SEQUENCE':
+                        cam_data["image_path"] = sequence_pattern(image_path)
+                        cam_data["keys"] = sequence_keys(cam, background.image_user, global_matrix)
                 cameras_data.append(cam_data)
 
             json_file_path = os.path.join(eyek_dir, "cameras.json")
@@ -210,9 +269,18 @@ class EYEK_exe(bpy.types.Operator):
                     bleed,
                     upscale
                     ]
-            popen = subprocess.Popen(args)
-            popen.wait()
+            popen = subprocess.Popen(args, stderr=subprocess.PIPE, text=True)
+            _, errors = popen.communicate()
             print("Time elapsed:", time.strftime("%H:%M:%S", time.gmtime(time.time()-start_time)))
+            if popen.returncode != 0:
+                message = "Eyek failed with exit code " + str(popen.returncode) + "."
+                for line in errors.splitlines():
+                    try:
+                        message = json.loads(line)["message"]
+                    except (ValueError, KeyError, TypeError):
+                        pass
+                self.report({'ERROR'}, message)
+                return {'CANCELLED'}
 
             if bpy.context.scene.eyek.autoreload:
                 for img in bpy.data.images:
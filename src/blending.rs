@@ -0,0 +1,343 @@
+use image::{Rgba, RgbaImage};
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+
+pub type Color = Rgba<u8>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Blending {
+    Average,
+    Median,
+    Mode,
+    Overlay,
+}
+impl Blending {
+    pub const NAMES: [&'static str; 4] = ["average", "median", "mode", "overlay"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "average" => Some(Blending::Average),
+            "median" => Some(Blending::Median),
+            "mode" => Some(Blending::Mode),
+            "overlay" => Some(Blending::Overlay),
+            _ => None,
+        }
+    }
+}
+
+/// Texture painted by one camera and how it mixes with the other ones.
+/// Layers are composited in order, the last one on top.
+pub struct Layer {
+    pub id: usize,
+    pub weight: f32,
+    pub opacity: f32,
+    pub texture: RgbaImage,
+}
+
+pub fn average(colors: Vec<Color>) -> Color {
+    let mut sum_r: usize = 0;
+    let mut sum_g: usize = 0;
+    let mut sum_b: usize = 0;
+    let mut sum_a: usize = 0;
+    colors.iter().for_each(|c| {
+        sum_r += c[0] as usize;
+        sum_g += c[1] as usize;
+        sum_b += c[2] as usize;
+        sum_a += c[3] as usize;
+    });
+    let r = (sum_r / colors.len()) as u8;
+    let g = (sum_g / colors.len()) as u8;
+    let b = (sum_b / colors.len()) as u8;
+    let a = (sum_a / colors.len()) as u8;
+    Rgba([r, g, b, a])
+}
+
+/// Colors count by weight times alpha, so faded edges cross-fade into other cameras.
+/// Alpha is averaged by weight.
+pub fn weighted_average(colors: &[(Color, f32)]) -> Color {
+    let total: f32 = colors.iter().map(|(_, w)| w).sum();
+    let total_color: f32 = colors.iter().map(|(c, w)| w * c[3] as f32).sum();
+    if total <= 0.0 || total_color <= 0.0 {
+        return average(colors.iter().map(|(c, _)| *c).collect());
+    }
+    let mut sum = [0.0_f32; 4];
+    for (c, w) in colors {
+        for (i, s) in sum.iter_mut().enumerate() {
+            *s += match i {
+                3 => c[3] as f32 * w,
+                _ => c[i] as f32 * w * c[3] as f32,
+            };
+        }
+    }
+    Rgba([
+        (sum[0] / total_color) as u8,
+        (sum[1] / total_color) as u8,
+        (sum[2] / total_color) as u8,
+        (sum[3] / total) as u8,
+    ])
+}
+
+pub fn median(colors: &mut [(Color, f32)]) -> Color {
+    colors.sort_by_key(|(c, _)| col_len(c));
+    let total: f32 = colors.iter().map(|(_, w)| w).sum();
+    let mut passed = 0.0;
+    for (c, w) in colors.iter() {
+        passed += w;
+        if passed > total / 2.0 {
+            return *c;
+        }
+    }
+    colors[colors.len() / 2].0
+}
+
+pub fn mode(colors: Vec<(Color, f32)>) -> Color {
+    let mut vec_mode = Vec::new();
+    let mut seen_map = HashMap::new();
+    let mut max_val = 0.0;
+    for (c, w) in colors {
+        let ctr = seen_map.entry(c).or_insert(0.0);
+        *ctr += w;
+        if *ctr > max_val {
+            max_val = *ctr;
+        }
+    }
+    for (key, val) in seen_map {
+        if val == max_val {
+            vec_mode.push(key);
+        }
+    }
+    vec_mode[0]
+}
+
+/// Straight alpha "over" compositing, the last color on top.
+pub fn overlay(colors: Vec<Color>) -> Color {
+    let mut bg = Rgba([0, 0, 0, 0]);
+    for fg in colors {
+        let bga = bg[3] as f32;
+        let bgr = bg[0] as f32;
+        let bgg = bg[1] as f32;
+        let bgb = bg[2] as f32;
+
+        let fga = fg[3] as f32;
+        let fgr = fg[0] as f32;
+        let fgg = fg[1] as f32;
+        let fgb = fg[2] as f32;
+
+        //Background alpha left visible under the foreground
+        let d = (255.0 - fga) * bga / 255.0;
+        let a = d + fga;
+        let r = (d * bgr + fgr * fga) / a;
+        let g = (d * bgg + fgg * fga) / a;
+        let b = (d * bgb + fgb * fga) / a;
+
+        bg = Rgba([r as u8, g as u8, b as u8, a as u8]);
+    }
+    bg
+}
+
+fn _mix_colors(source: Rgba<u8>, target: &Rgba<u8>) -> Rgba<u8> {
+    let sr = source[0];
+    let sg = source[1];
+    let sb = source[2];
+    let tr = target[0];
+    let tg = target[1];
+    let tb = target[2];
+    let ta = target[3];
+
+    if ta == 0 {
+        source
+    } else {
+        Rgba([sr / 2 + tr / 2, sg / 2 + tg / 2, sb / 2 + tb / 2, 255])
+    }
+}
+
+/// Blends camera layers. Opacity scales alpha of every layer,
+/// weight counts in average, median and mode, where cameras of weight 0 are left out.
+pub fn combine_layers(layers: Vec<Layer>, blending: &Blending) -> (RgbaImage, bool) {
+    let (img_res_x, img_res_y) = layers[0].texture.dimensions();
+    let mut mono_texture = RgbaImage::new(img_res_x, img_res_y);
+    let mut texture_is_empty = true;
+    for y in 0..img_res_y {
+        for x in 0..img_res_x {
+            let mut colors = Vec::<(Color, f32)>::new();
+            for layer in &layers {
+                let mut col = *layer.texture.get_pixel(x, y);
+                col[3] = (col[3] as f32 * layer.opacity).round() as u8;
+                let weighted = *blending != Blending::Overlay;
+                if col[3] != 0 && (!weighted || layer.weight > 0.0) {
+                    colors.push((col, layer.weight));
+                }
+            }
+            if !colors.is_empty() {
+                texture_is_empty = false;
+                let m = match &blending {
+                    Blending::Average => weighted_average(&colors),
+                    Blending::Median => median(&mut colors),
+                    Blending::Mode => mode(colors),
+                    Blending::Overlay => overlay(colors.into_iter().map(|(c, _)| c).collect()),
+                };
+                mono_texture.put_pixel(x, y, m)
+            }
+        }
+    }
+    (mono_texture, texture_is_empty)
+}
+
+fn blend_pixel_with_neigbhours(texture: &RgbaImage, x: u32, y: u32, limit: u8) -> Rgba<u8> {
+    let ways = [
+        [0, 1],
+        [1, 1],
+        [1, 0],
+        [1, -1],
+        [0, -1],
+        [-1, 1],
+        [-1, 0],
+        [-1, -1],
+    ];
+    let bx = texture.dimensions().0 as i32;
+    let by = texture.dimensions().1 as i32;
+    let mut neibs_count = 0;
+    let mut r = 0;
+    let mut g = 0;
+    let mut b = 0;
+    let mut a = 0;
+    for way in ways.iter() {
+        let xp = x as i32 + way[0];
+        let yp = y as i32 + way[1];
+        if xp >= 0 && xp < bx && yp >= 0 && yp < by {
+            let col = texture.get_pixel(xp as u32, yp as u32);
+            if col[3] != 0 {
+                neibs_count += 1;
+                r += col[0] as u32;
+                g += col[1] as u32;
+                b += col[2] as u32;
+                a += col[3] as u32;
+            }
+        }
+    }
+    if neibs_count > limit as u32 {
+        r /= neibs_count;
+        g /= neibs_count;
+        b /= neibs_count;
+        a /= neibs_count;
+        Rgba([r as u8, g as u8, b as u8, a as u8])
+    } else {
+        *texture.get_pixel(x, y)
+    }
+}
+
+pub fn expand_pixels(texture: &mut RgbaImage, limit: u8) {
+    let (width, height) = texture.dimensions();
+    let mut future_pixels = Vec::<(u32, u32, Rgba<u8>)>::new();
+    for v in 0..(height as usize) {
+        for u in 0..(width as usize) {
+            let current_color = *texture.get_pixel(u as u32, v as u32);
+            if current_color[3] == 0 {
+                let blended_color = blend_pixel_with_neigbhours(texture, u as u32, v as u32, limit);
+                if blended_color[3] != 0 {
+                    future_pixels.push((u as u32, v as u32, blended_color));
+                }
+            }
+        }
+    }
+    for p in future_pixels {
+        texture.put_pixel(p.0, p.1, p.2);
+    }
+}
+
+fn col_len(c: &Color) -> usize {
+    (((c[0] as usize).pow(2)
+        + (c[1] as usize).pow(2)
+        + (c[2] as usize).pow(2)
+        + (c[3] as usize).pow(2)) as f32)
+        .sqrt() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Color = Rgba([255, 0, 0, 255]);
+    const HALF_BLUE: Color = Rgba([0, 0, 255, 128]);
+
+    #[test]
+    fn overlay_opaque_on_top() {
+        assert_eq!(overlay(vec![HALF_BLUE, RED]), RED);
+        assert_eq!(overlay(vec![RED]), RED);
+    }
+
+    #[test]
+    fn overlay_partial_alpha() {
+        assert_eq!(overlay(vec![RED, HALF_BLUE]), Rgba([127, 0, 128, 255]));
+        //Two half transparent layers stay half transparent where they overlap
+        let half_red = Rgba([255, 0, 0, 128]);
+        assert_eq!(overlay(vec![half_red, HALF_BLUE]), Rgba([84, 0, 170, 191]));
+    }
+
+    // Two cameras fading out towards each other, as feathering leaves them.
+    fn feathered_layers() -> Vec<Layer> {
+        let ramp = |color: [u8; 3], alpha: [u8; 3]| {
+            RgbaImage::from_fn(3, 1, |x, _| {
+                Rgba([color[0], color[1], color[2], alpha[x as usize]])
+            })
+        };
+        vec![
+            Layer {
+                id: 0,
+                weight: 1.0,
+                opacity: 1.0,
+                texture: ramp([255, 0, 0], [255, 128, 0]),
+            },
+            Layer {
+                id: 1,
+                weight: 1.0,
+                opacity: 1.0,
+                texture: ramp([0, 0, 255], [0, 128, 255]),
+            },
+        ]
+    }
+
+    #[test]
+    fn feathered_layers_cross_fade() {
+        for blending in [Blending::Overlay, Blending::Average] {
+            let (texture, empty) = combine_layers(feathered_layers(), &blending);
+            assert!(!empty);
+            assert_eq!(*texture.get_pixel(0, 0), RED);
+            assert_eq!(*texture.get_pixel(2, 0), Rgba([0, 0, 255, 255]));
+            let seam = texture.get_pixel(1, 0);
+            assert!(seam[0] > 64 && seam[2] > 64, "{:?} {:?}", blending, seam);
+        }
+        let (overlay, _) = combine_layers(feathered_layers(), &Blending::Overlay);
+        assert_eq!(*overlay.get_pixel(1, 0), Rgba([84, 0, 170, 191]));
+        let (average, _) = combine_layers(feathered_layers(), &Blending::Average);
+        assert_eq!(*average.get_pixel(1, 0), Rgba([127, 0, 127, 128]));
+    }
+
+    #[test]
+    fn weighted_average_partial_alpha() {
+        //Weight 1 opaque red and weight 2 half blue
+        let color = weighted_average(&[(RED, 1.0), (HALF_BLUE, 2.0)]);
+        assert_eq!(color, Rgba([127, 0, 127, 170]));
+        //Transparent colors don't tint the average
+        let clear = Rgba([0, 255, 0, 0]);
+        assert_eq!(
+            weighted_average(&[(RED, 1.0), (clear, 5.0)]),
+            Rgba([255, 0, 0, 42])
+        );
+    }
+
+    #[test]
+    fn median_by_weight() {
+        //Sorted by length with alpha, a half transparent color is the shortest
+        let dark = Rgba([10, 10, 10, 128]);
+        let mid = Rgba([100, 100, 100, 255]);
+        let bright = Rgba([200, 200, 200, 255]);
+        assert_eq!(median(&mut [(bright, 1.0), (dark, 1.0), (mid, 1.0)]), mid);
+        assert_eq!(
+            median(&mut [(bright, 3.0), (dark, 1.0), (mid, 1.0)]),
+            bright
+        );
+        assert_eq!(median(&mut [(bright, 1.0), (dark, 2.5), (mid, 1.0)]), dark);
+    }
+}
@@ -0,0 +1,812 @@
+use crate::camera_bundler::load_bundler;
+use crate::camera_colmap::load_colmap;
+use crate::camera_meshroom::load_meshroom;
+use crate::error::{BoxError, EyekError, Result};
+use bvh::nalgebra::geometry::{Quaternion, Rotation3, UnitQuaternion};
+use bvh::nalgebra::{Matrix3, Vector3, Vector4};
+use serde_derive::Deserialize;
+use serde_json::Value;
+use std::f32::consts::PI;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+pub struct Coords {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+#[derive(Debug, Deserialize)]
+pub struct Quat {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Coords2 {
+    pub x: f32,
+    pub y: f32,
+}
+/// Which image dimension the camera angle (or orthographic scale) spans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorFit {
+    Auto,
+    Horizontal,
+    Vertical,
+}
+/// How the camera maps directions to the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CameraType {
+    Perspective,
+    Orthographic,
+    /// Full 360° by 180° latitude-longitude panorama.
+    Equirectangular,
+    /// Fisheye lenses, `fov_x` is the angle across the image width
+    /// (or height with vertical `sensor_fit`) and may exceed 180°.
+    FisheyeEquidistant,
+    FisheyeEquisolid,
+    FisheyeStereographic,
+}
+/// Lens distortion in OpenCV conventions: coefficients act on image coordinates
+/// normalized by the focal length, with y pointing down.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "model", rename_all = "lowercase")]
+pub enum Distortion {
+    /// Brown–Conrady radial (k1–k3) and tangential (p1, p2) distortion.
+    Brown {
+        #[serde(default)]
+        k1: f32,
+        #[serde(default)]
+        k2: f32,
+        #[serde(default)]
+        k3: f32,
+        #[serde(default)]
+        p1: f32,
+        #[serde(default)]
+        p2: f32,
+    },
+    /// Kannala–Brandt fisheye distortion (k1–k4) of the angle from the optical axis.
+    Fisheye {
+        #[serde(default)]
+        k1: f32,
+        #[serde(default)]
+        k2: f32,
+        #[serde(default)]
+        k3: f32,
+        #[serde(default)]
+        k4: f32,
+    },
+}
+impl Distortion {
+    /// Maps an ideal pinhole point to where the lens puts it.
+    pub fn distort(&self, x: f32, y: f32) -> [f32; 2] {
+        let r2 = x * x + y * y;
+        match *self {
+            Distortion::Brown { k1, k2, k3, p1, p2 } => {
+                let radial = 1.0 + r2 * (k1 + r2 * (k2 + r2 * k3));
+                [
+                    x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x),
+                    y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y,
+                ]
+            }
+            Distortion::Fisheye { k1, k2, k3, k4 } => {
+                let r = r2.sqrt();
+                if r < f32::EPSILON {
+                    return [x, y];
+                }
+                let theta = r.atan();
+                let t2 = theta * theta;
+                let theta_d = theta * (1.0 + t2 * (k1 + t2 * (k2 + t2 * (k3 + t2 * k4))));
+                [x * theta_d / r, y * theta_d / r]
+            }
+        }
+    }
+}
+/// Order the `rotation_euler` angles are applied in, as Blender rotation modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum EulerOrder {
+    #[default]
+    Xyz,
+    Xzy,
+    Yxz,
+    Yzx,
+    Zxy,
+    Zyx,
+}
+impl EulerOrder {
+    pub fn rotation(self, angles: &Coords) -> UnitQuaternion<f32> {
+        let x = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), angles.x);
+        let y = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), angles.y);
+        let z = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), angles.z);
+        //First axis is applied first
+        match self {
+            EulerOrder::Xyz => z * y * x,
+            EulerOrder::Xzy => y * z * x,
+            EulerOrder::Yxz => z * x * y,
+            EulerOrder::Yzx => x * z * y,
+            EulerOrder::Zxy => y * x * z,
+            EulerOrder::Zyx => x * y * z,
+        }
+    }
+}
+/// Up axis of the world the camera pose is given in. Eyek works Y up,
+/// the add-on converts Blender's Z up world before exporting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum UpAxis {
+    #[default]
+    Y,
+    Z,
+}
+/// OpenCV extrinsics `x = K (R X + t)`, camera looking down +Z with Y down.
+#[derive(Debug, Deserialize)]
+pub struct OpenCvPose {
+    #[serde(rename = "R")]
+    pub r: [[f32; 3]; 3],
+    pub t: [f32; 3],
+    /// Intrinsics in pixels, replaces `fov_x`, `principal_point` and `pixel_aspect`.
+    #[serde(rename = "K", default)]
+    pub k: Option<[[f32; 3]; 3]>,
+}
+fn default_pixel_aspect() -> f32 {
+    1.0
+}
+fn default_one() -> f32 {
+    1.0
+}
+#[derive(Debug, Deserialize)]
+pub struct VecCameraJSON {
+    pub data: Vec<CameraJSON>,
+}
+/// One camera of `cameras.json`. An animated camera also has `keys`, a list of
+/// objects with an integer `frame` and any camera fields that change at that frame.
+/// It is expanded into one camera per key, see `expand_keys`.
+#[derive(Debug, Deserialize)]
+pub struct CameraJSON {
+    /// Optional with a 4x4 `matrix_world` or `opencv`.
+    #[serde(default)]
+    pub location: Option<Coords>,
+    /// Camera rotation is one of `rotation_euler`, `rotation_quaternion`,
+    /// `matrix_world` (3x3 or 4x4 rows) or `opencv`.
+    #[serde(default)]
+    pub rotation_euler: Option<Coords>,
+    #[serde(default)]
+    pub euler_order: EulerOrder,
+    #[serde(default)]
+    pub rotation_quaternion: Option<Quat>,
+    #[serde(default)]
+    pub matrix_world: Option<Vec<Vec<f32>>>,
+    #[serde(default)]
+    pub opencv: Option<OpenCvPose>,
+    #[serde(default)]
+    pub up_axis: UpAxis,
+    /// Defaults to the scale of `matrix_world`, or 1.
+    #[serde(default)]
+    pub scale: Option<Coords>,
+    /// Without it a positive `fov_x` is perspective and a negative one orthographic.
+    #[serde(default)]
+    pub camera_type: Option<CameraType>,
+    /// Optional with `opencv` intrinsics and for equirectangular cameras.
+    #[serde(default)]
+    pub fov_x: Option<f32>,
+    pub limit_near: f32,
+    pub limit_far: f32,
+    pub image_path: String,
+    /// Grayscale image, or the alpha of an image with alpha, multiplied into the projection.
+    #[serde(default)]
+    pub mask_path: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Lens shift in fractions of the `sensor_fit` image dimension, as in Blender.
+    /// Without a fit, or with auto, in fractions of the larger one.
+    #[serde(default)]
+    pub shift_x: f32,
+    #[serde(default)]
+    pub shift_y: f32,
+    /// Optical center in pixels from the top left corner of the image.
+    #[serde(default)]
+    pub principal_point: Option<Coords2>,
+    /// Without it `fov_x` is horizontal and orthographic `scale` x and y are used as is.
+    #[serde(default)]
+    pub sensor_fit: Option<SensorFit>,
+    /// Pixel width divided by pixel height.
+    #[serde(default = "default_pixel_aspect")]
+    pub pixel_aspect: f32,
+    /// Distortion of the source photo. Only used by perspective cameras.
+    #[serde(default)]
+    pub distortion: Option<Distortion>,
+    /// Share of the camera in average, median and mode blending.
+    #[serde(default = "default_one")]
+    pub weight: f32,
+    /// Scales alpha of the camera before blending, 0..1.
+    #[serde(default = "default_one")]
+    pub opacity: f32,
+    /// Cameras with higher priority are stacked on top, ties keep the file order.
+    #[serde(default)]
+    pub priority: i32,
+}
+#[derive(Debug, Clone)]
+pub struct CameraRaw {
+    pub id: usize,
+    pub pos: [f32; 3],
+    pub rot: UnitQuaternion<f32>,
+    pub scale: [f32; 3],
+    pub camera_type: CameraType,
+    pub fov_x: f32,
+    pub limit_near: f32,
+    pub limit_far: f32,
+    pub image_path: String,
+    pub mask_path: Option<String>,
+    pub name: String,
+    pub shift: [f32; 2],
+    pub principal_point: Option<[f32; 2]>,
+    pub sensor_fit: Option<SensorFit>,
+    pub pixel_aspect: f32,
+    pub distortion: Option<Distortion>,
+    pub weight: f32,
+    pub opacity: f32,
+    pub priority: i32,
+}
+
+/// Clip range of cameras imported from reconstructions, which have none.
+pub const DEFAULT_NEAR: f32 = 0.01;
+pub const DEFAULT_FAR: f32 = 10000.0;
+
+pub const CAMERA_FILES: [&str; 4] = ["cameras.json", "cameras.txt", "cameras.sfm", "bundle.out"];
+
+pub(crate) fn cameras_error(path: &Path, source: BoxError) -> EyekError {
+    EyekError::Cameras {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+/// Turns OpenCV camera axes (Y down, looking down +Z) into eyek ones (Y up, looking down -Z).
+pub(crate) fn flip_opencv() -> UnitQuaternion<f32> {
+    UnitQuaternion::from_axis_angle(&Vector3::x_axis(), PI)
+}
+
+pub(crate) fn image_stem(image_path: &str) -> Option<String> {
+    Path::new(image_path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+}
+
+pub fn load_cameras(path_data: &str, path_images: Option<&str>) -> Result<Vec<CameraRaw>> {
+    let dir = Path::new(path_data);
+    let path = CAMERA_FILES
+        .iter()
+        .map(|f| dir.join(f))
+        .find(|p| p.is_file())
+        .unwrap_or_else(|| dir.join(CAMERA_FILES[0]));
+    load_camera_file(&path, path_images.map(Path::new))
+}
+
+/// Loads eyek `cameras.json`, a COLMAP text model (`cameras.txt` next to `images.txt`),
+/// Meshroom `cameras.sfm` or Bundler `bundle.out` (with `list.txt` next to it).
+/// `images` is the photo directory of a COLMAP model, see `load_colmap`.
+pub fn load_camera_file(path: &Path, images: Option<&Path>) -> Result<Vec<CameraRaw>> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("txt") => load_colmap(path, images),
+        Some("sfm") => load_meshroom(path),
+        Some("out") => load_bundler(path),
+        _ => load_cameras_json(path),
+    }
+}
+
+struct Pose {
+    pos: [f32; 3],
+    rot: UnitQuaternion<f32>,
+    scale: [f32; 3],
+}
+
+fn camera_pose(cam: &CameraJSON) -> std::result::Result<Pose, String> {
+    let given = [
+        cam.rotation_euler.is_some(),
+        cam.rotation_quaternion.is_some(),
+        cam.matrix_world.is_some(),
+        cam.opencv.is_some(),
+    ];
+    if given.iter().filter(|g| **g).count() != 1 {
+        return Err(
+            "expected exactly one of rotation_euler, rotation_quaternion, matrix_world or opencv"
+                .to_string(),
+        );
+    }
+    let location = cam.location.as_ref().map(|l| [l.x, l.y, l.z]);
+    let scale = cam.scale.as_ref().map(|s| [s.x, s.y, s.z]);
+    let (pos, rot, scale) = match (
+        &cam.rotation_euler,
+        &cam.rotation_quaternion,
+        &cam.matrix_world,
+        &cam.opencv,
+    ) {
+        (Some(euler), _, _, _) => (location, cam.euler_order.rotation(euler), scale),
+        (_, Some(q), _, _) => (
+            location,
+            UnitQuaternion::from_quaternion(Quaternion::from(Vector4::new(q.x, q.y, q.z, q.w))),
+            scale,
+        ),
+        (_, _, Some(m), _) => {
+            let size = m.len();
+            if !(size == 3 || size == 4) || m.iter().any(|row| row.len() != size) {
+                return Err("matrix_world must be 3x3 or 4x4".to_string());
+            }
+            let mut rot = Matrix3::from_fn(|r, c| m[r][c]);
+            let columns = [
+                rot.column(0).norm(),
+                rot.column(1).norm(),
+                rot.column(2).norm(),
+            ];
+            if columns.iter().any(|n| *n < f32::EPSILON) {
+                return Err("matrix_world is singular".to_string());
+            }
+            for (c, n) in columns.iter().enumerate() {
+                rot.column_mut(c).unscale_mut(*n);
+            }
+            let translation = match size {
+                4 => Some([m[0][3], m[1][3], m[2][3]]),
+                _ => None,
+            };
+            (
+                location.or(translation),
+                UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(rot)),
+                scale.or(Some(columns)),
+            )
+        }
+        (_, _, _, Some(cv)) => {
+            let world_to_cam =
+                Rotation3::from_matrix_unchecked(Matrix3::from_fn(|r, c| cv.r[r][c]));
+            let pos = world_to_cam.inverse_transform_vector(&-Vector3::from(cv.t));
+            (
+                Some([pos.x, pos.y, pos.z]),
+                UnitQuaternion::from_rotation_matrix(&world_to_cam).inverse() * flip_opencv(),
+                scale,
+            )
+        }
+        _ => unreachable!(),
+    };
+    let pos = pos.ok_or_else(|| "missing location".to_string())?;
+    let (pos, rot) = match cam.up_axis {
+        UpAxis::Y => (pos, rot),
+        UpAxis::Z => {
+            //Same conversion as the add-on: forward -Z, up Y
+            let to_y_up = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), -PI / 2.0);
+            let pos = to_y_up * Vector3::from(pos);
+            ([pos.x, pos.y, pos.z], to_y_up * rot)
+        }
+    };
+    Ok(Pose {
+        pos,
+        rot,
+        scale: scale.unwrap_or([1.0, 1.0, 1.0]),
+    })
+}
+
+const ROTATION_FIELDS: [&str; 4] = [
+    "rotation_euler",
+    "rotation_quaternion",
+    "matrix_world",
+    "opencv",
+];
+
+/// Replaces every run of `#` with the frame number padded to its length,
+/// as in Blender image sequences: `shot_####.jpg` is `shot_0012.jpg` at frame 12.
+pub fn frame_path(pattern: &str, frame: i64) -> String {
+    let mut path = String::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '#' {
+            path.push(c);
+            continue;
+        }
+        let mut width = 1;
+        while chars.next_if_eq(&'#').is_some() {
+            width += 1;
+        }
+        path.push_str(&format!("{:0width$}", frame, width = width));
+    }
+    path
+}
+
+/// Expands a camera with `keys` into one camera per key. Key fields replace camera fields,
+/// a key rotation replaces any rotation of the camera. `image_path` and `mask_path` are
+/// frame patterns and an explicit `name` gets the frame appended.
+pub fn expand_keys(camera: Value) -> std::result::Result<Vec<Value>, String> {
+    let mut camera = match camera {
+        Value::Object(camera) => camera,
+        _ => return Err("expected a JSON object".to_string()),
+    };
+    let keys = match camera.remove("keys") {
+        None => return Ok(vec![Value::Object(camera)]),
+        Some(Value::Array(keys)) => keys,
+        Some(_) => return Err("\"keys\" must be a list".to_string()),
+    };
+    let mut cameras = Vec::new();
+    for key in keys {
+        let mut key = match key {
+            Value::Object(key) => key,
+            _ => return Err("every key must be a JSON object".to_string()),
+        };
+        let frame = match key.remove("frame").and_then(|f| f.as_i64()) {
+            Some(frame) => frame,
+            None => return Err("every key needs an integer \"frame\"".to_string()),
+        };
+        let mut frame_camera = camera.clone();
+        if ROTATION_FIELDS.iter().any(|f| key.contains_key(*f)) {
+            for field in ROTATION_FIELDS {
+                frame_camera.remove(field);
+            }
+        }
+        frame_camera.extend(key);
+        for field in ["image_path", "mask_path"] {
+            if let Some(Value::String(pattern)) = frame_camera.get(field) {
+                let path = frame_path(pattern, frame);
+                frame_camera.insert(field.to_string(), Value::String(path));
+            }
+        }
+        if let Some(Value::String(name)) = frame_camera.get("name") {
+            let name = format!("{}_{}", name, frame);
+            frame_camera.insert("name".to_string(), Value::String(name));
+        }
+        cameras.push(Value::Object(frame_camera));
+    }
+    Ok(cameras)
+}
+
+pub fn load_cameras_json(path: &Path) -> Result<Vec<CameraRaw>> {
+    let value: Value = fs::File::open(path)
+        .map_err(serde_json::Error::io)
+        .and_then(|file_json| serde_json::from_reader(io::BufReader::new(file_json)))
+        .map_err(|e| cameras_error(path, e.into()))?;
+    let entries = match value.get("data") {
+        Some(Value::Array(entries)) => entries,
+        _ => return Err(cameras_error(path, "missing list \"data\"".into())),
+    };
+    let mut cameras_json = Vec::<CameraJSON>::new();
+    for (entry_id, entry) in entries.iter().enumerate() {
+        let entry_error =
+            |reason: String| cameras_error(path, format!("camera {}: {}", entry_id, reason).into());
+        for camera in expand_keys(entry.clone()).map_err(entry_error)? {
+            cameras_json
+                .push(serde_json::from_value(camera).map_err(|e| entry_error(e.to_string()))?);
+        }
+    }
+    let mut cameras = Vec::<CameraRaw>::new();
+    for (id, cam) in cameras_json.into_iter().enumerate() {
+        let camera_error =
+            |reason: String| cameras_error(path, format!("camera {}: {}", id, reason).into());
+        let Pose { pos, rot, scale } = camera_pose(&cam).map_err(camera_error)?;
+        if !cam.weight.is_finite() || cam.weight < 0.0 {
+            return Err(camera_error(format!(
+                "weight must be a non-negative number, got {}",
+                cam.weight
+            )));
+        }
+        if !(0.0..=1.0).contains(&cam.opacity) {
+            return Err(camera_error(format!(
+                "opacity must be in 0..1, got {}",
+                cam.opacity
+            )));
+        }
+        let mut principal_point = cam.principal_point.map(|p| [p.x, p.y]);
+        let mut pixel_aspect = cam.pixel_aspect;
+        let mut sensor_fit = cam.sensor_fit;
+        let k = cam.opencv.as_ref().and_then(|cv| cv.k);
+        let fov_x = match (k, cam.fov_x) {
+            (Some(k), _) => {
+                let (width, _) = image::image_dimensions(&cam.image_path).map_err(|e| {
+                    EyekError::CameraImage {
+                        path: (&cam.image_path).into(),
+                        source: e,
+                    }
+                })?;
+                let [fx, fy] = [k[0][0], k[1][1]];
+                principal_point = Some([k[0][2], k[1][2]]);
+                pixel_aspect = fy / fx;
+                sensor_fit = Some(SensorFit::Horizontal);
+                2.0 * (width as f32 / (2.0 * fx)).atan()
+            }
+            (None, Some(fov_x)) => fov_x,
+            (None, None) if cam.camera_type == Some(CameraType::Equirectangular) => 0.0,
+            (None, None) => return Err(camera_error("missing fov_x".to_string())),
+        };
+        let camera_type = match cam.camera_type {
+            Some(camera_type) => camera_type,
+            None if fov_x > 0.0 => CameraType::Perspective,
+            None => CameraType::Orthographic,
+        };
+        let limit_near = cam.limit_near;
+        let limit_far = cam.limit_far;
+        let name = match cam.name {
+            Some(name) => name,
+            None => image_stem(&cam.image_path).unwrap_or_else(|| id.to_string()),
+        };
+        let image_path = cam.image_path;
+
+        cameras.push(CameraRaw {
+            id,
+            pos,
+            rot,
+            scale,
+            camera_type,
+            fov_x,
+            limit_near,
+            limit_far,
+            image_path,
+            mask_path: cam.mask_path,
+            name,
+            shift: [cam.shift_x, cam.shift_y],
+            principal_point,
+            sensor_fit,
+            pixel_aspect,
+            distortion: cam.distortion,
+            weight: cam.weight,
+            opacity: cam.opacity,
+            priority: cam.priority,
+        });
+    }
+
+    Ok(cameras)
+}
+
+/// Fixtures shared by the camera loader tests.
+#[cfg(test)]
+pub(crate) mod test_util {
+    use bvh::nalgebra::geometry::{Rotation3, UnitQuaternion};
+    use bvh::nalgebra::{Matrix3, Vector3};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// World to camera rotation of the known camera, 90° about Y.
+    pub const WORLD_TO_CAM: [[f32; 3]; 3] = [[0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [-1.0, 0.0, 0.0]];
+    /// Center of the known camera.
+    pub const CENTER: [f32; 3] = [3.0, 1.0, 2.0];
+
+    pub fn world_to_cam() -> Rotation3<f32> {
+        Rotation3::from_matrix_unchecked(Matrix3::from_fn(|r, c| WORLD_TO_CAM[r][c]))
+    }
+
+    /// Translation `t = -R C` of the known camera.
+    pub fn translation() -> [f32; 3] {
+        let t = -(world_to_cam() * Vector3::from(CENTER));
+        [t.x, t.y, t.z]
+    }
+
+    pub fn assert_near(a: Vector3<f32>, b: [f32; 3]) {
+        assert!((a - Vector3::from(b)).norm() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    /// Checks the pose against the known camera, with the world directions
+    /// its -Z forward and +Y up axes are expected to point at.
+    pub fn assert_known_pose(
+        pos: [f32; 3],
+        rot: UnitQuaternion<f32>,
+        forward: [f32; 3],
+        up: [f32; 3],
+    ) {
+        assert_near(Vector3::from(pos), CENTER);
+        assert_near(rot * Vector3::new(0.0, 0.0, -1.0), forward);
+        assert_near(rot * Vector3::new(0.0, 1.0, 0.0), up);
+    }
+
+    /// Directory in the system temp directory, removed when dropped.
+    pub struct TempDir(PathBuf);
+    impl TempDir {
+        pub fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("eyek_{}_{}", name, std::process::id()));
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.0
+        }
+
+        /// Writes `contents` to the file `name` and returns its path.
+        pub fn write(&self, name: &str, contents: &str) -> PathBuf {
+            let path = self.0.join(name);
+            fs::write(&path, contents).unwrap();
+            path
+        }
+    }
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_util::{assert_known_pose, assert_near, translation, WORLD_TO_CAM};
+    use super::*;
+    use serde_json::json;
+
+    fn pose(mut camera: Value) -> Pose {
+        camera["limit_near"] = json!(0.1);
+        camera["limit_far"] = json!(100.0);
+        camera["image_path"] = json!("shot.jpg");
+        camera_pose(&serde_json::from_value(camera).unwrap()).unwrap()
+    }
+
+    fn assert_xy([x, y]: [f32; 2], b: [f32; 2]) {
+        assert_near(Vector3::new(x, y, 0.0), [b[0], b[1], 0.0]);
+    }
+
+    fn brown(k1: f32, p1: f32) -> Distortion {
+        Distortion::Brown {
+            k1,
+            k2: 0.0,
+            k3: 0.0,
+            p1,
+            p2: 0.0,
+        }
+    }
+
+    #[test]
+    fn distort_zero_coefficients() {
+        let fisheye = Distortion::Fisheye {
+            k1: 0.0,
+            k2: 0.0,
+            k3: 0.0,
+            k4: 0.0,
+        };
+        for [x, y] in [[0.0, 0.0], [0.3, -0.2], [-1.5, 2.0]] {
+            assert_eq!(brown(0.0, 0.0).distort(x, y), [x, y]);
+            //Plain equidistant fisheye: image radius is the angle from the axis
+            let r = x.hypot(y);
+            let scale = match r > 0.0 {
+                true => r.atan() / r,
+                false => 1.0,
+            };
+            assert_xy(fisheye.distort(x, y), [x * scale, y * scale]);
+        }
+    }
+
+    #[test]
+    fn distort_brown() {
+        assert_xy(brown(0.1, 0.0).distort(0.5, 0.0), [0.5125, 0.0]);
+        assert_xy(brown(0.0, 0.01).distort(0.0, 0.5), [0.0, 0.5075]);
+    }
+
+    #[test]
+    fn euler_xyz_matches_from_euler_angles() {
+        let angles = Coords {
+            x: 0.3,
+            y: -1.2,
+            z: 2.5,
+        };
+        let expected = UnitQuaternion::from_euler_angles(angles.x, angles.y, angles.z);
+        assert!(EulerOrder::Xyz.rotation(&angles).angle_to(&expected) < 1e-5);
+    }
+
+    #[test]
+    fn euler_orders() {
+        let angles = Coords {
+            x: 0.3,
+            y: -1.2,
+            z: 2.5,
+        };
+        let negated = Coords {
+            x: -angles.x,
+            y: -angles.y,
+            z: -angles.z,
+        };
+        //Reversed order undoes the negated rotation
+        let pairs = [
+            (EulerOrder::Xyz, EulerOrder::Zyx),
+            (EulerOrder::Xzy, EulerOrder::Yzx),
+            (EulerOrder::Yxz, EulerOrder::Zxy),
+        ];
+        for (order, reversed) in pairs {
+            let rot = order.rotation(&angles) * reversed.rotation(&negated);
+            assert!(rot.angle() < 1e-5, "{:?} {:?}", order, reversed);
+        }
+        assert!(
+            EulerOrder::Xyz
+                .rotation(&angles)
+                .angle_to(&EulerOrder::Zyx.rotation(&angles))
+                > 0.1
+        );
+    }
+
+    #[test]
+    fn pose_opencv() {
+        let pose = pose(json!({"opencv": {"R": WORLD_TO_CAM, "t": translation()}}));
+        //OpenCV +Z forward and +Y down
+        assert_known_pose(pose.pos, pose.rot, [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]);
+    }
+
+    #[test]
+    fn pose_z_up() {
+        //Blender camera behind the origin on -Y, looking along +Y with Z up
+        let pose = pose(json!({
+            "location": {"x": 0.0, "y": -5.0, "z": 0.0},
+            "rotation_euler": {"x": PI / 2.0, "y": 0.0, "z": 0.0},
+            "up_axis": "Z",
+        }));
+        assert_near(Vector3::from(pose.pos), [0.0, 0.0, 5.0]);
+        assert_near(pose.rot * Vector3::new(0.0, 0.0, -1.0), [0.0, 0.0, -1.0]);
+        assert_near(pose.rot * Vector3::new(0.0, 1.0, 0.0), [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn pose_matrix_world() {
+        let pose = pose(json!({
+            "matrix_world": [
+                [0.0, 0.0, 2.0, 1.0],
+                [0.0, 2.0, 0.0, 2.0],
+                [-2.0, 0.0, 0.0, 3.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }));
+        assert_near(Vector3::from(pose.pos), [1.0, 2.0, 3.0]);
+        assert_near(Vector3::from(pose.scale), [2.0, 2.0, 2.0]);
+        assert_near(pose.rot * Vector3::new(0.0, 0.0, -1.0), [-1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn pose_needs_one_rotation() {
+        let camera: CameraJSON = serde_json::from_value(json!({
+            "location": {"x": 0.0, "y": 0.0, "z": 0.0},
+            "rotation_euler": {"x": 0.0, "y": 0.0, "z": 0.0},
+            "rotation_quaternion": {"x": 0.0, "y": 0.0, "z": 0.0, "w": 1.0},
+            "limit_near": 0.1,
+            "limit_far": 100.0,
+            "image_path": "shot.jpg",
+        }))
+        .unwrap();
+        assert!(camera_pose(&camera).is_err());
+    }
+
+    #[test]
+    fn frame_path_padding() {
+        assert_eq!(frame_path("shot_####.jpg", 12), "shot_0012.jpg");
+        assert_eq!(frame_path("shot_#.jpg", 12), "shot_12.jpg");
+        assert_eq!(frame_path("#_##.png", 3), "3_03.png");
+        assert_eq!(frame_path("shot.jpg", 3), "shot.jpg");
+        assert_eq!(frame_path("shot_###.jpg", -4), "shot_-04.jpg");
+    }
+
+    #[test]
+    fn expand_keys_frames() {
+        let camera = json!({
+            "name": "cam",
+            "location": {"x": 0.0, "y": 0.0, "z": 0.0},
+            "rotation_euler": {"x": 0.0, "y": 0.0, "z": 0.0},
+            "image_path": "shot_###.jpg",
+            "keys": [
+                {"frame": 1, "location": {"x": 1.0, "y": 0.0, "z": 0.0}},
+                {"frame": 2, "rotation_quaternion": {"x": 0.0, "y": 0.0, "z": 0.0, "w": 1.0}},
+            ],
+        });
+        let cameras = expand_keys(camera).unwrap();
+        assert_eq!(cameras.len(), 2);
+        assert_eq!(cameras[0]["name"], "cam_1");
+        assert_eq!(cameras[0]["image_path"], "shot_001.jpg");
+        assert_eq!(cameras[0]["location"]["x"], 1.0);
+        assert!(cameras[0].get("rotation_euler").is_some());
+        assert!(cameras[0].get("keys").is_none());
+        assert_eq!(cameras[1]["name"], "cam_2");
+        assert_eq!(cameras[1]["location"]["x"], 0.0);
+        //Key rotation replaces the camera one
+        assert!(cameras[1].get("rotation_euler").is_none());
+        assert!(cameras[1].get("rotation_quaternion").is_some());
+        assert!(cameras.iter().all(|c| c.get("frame").is_none()));
+    }
+
+    #[test]
+    fn expand_keys_without_keys() {
+        let camera = json!({"image_path": "shot_###.jpg"});
+        assert_eq!(expand_keys(camera.clone()).unwrap(), vec![camera]);
+        assert!(expand_keys(json!([])).is_err());
+        assert!(expand_keys(json!({"keys": {}})).is_err());
+        assert!(expand_keys(json!({"keys": [{"location": {}}]})).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::camera::{
+    cameras_error, image_stem, CameraRaw, CameraType, Distortion, SensorFit, DEFAULT_FAR,
+    DEFAULT_NEAR,
+};
+use crate::error::{BoxError, EyekError, Result};
+use bvh::nalgebra::geometry::{Rotation3, UnitQuaternion};
+use bvh::nalgebra::{Matrix3, Vector3};
+use std::fs;
+use std::path::Path;
+
+// Each camera is `f k1 k2`, three rows of R and t.
+const CAMERA_VALUES: usize = 15;
+
+/// Loads a Bundler `bundle.out` with image names from the `list.txt` next to it.
+/// Cameras with zero focal length were not reconstructed and are skipped.
+/// Image paths are resolved against the list file directory.
+pub fn load_bundler(path: &Path) -> Result<Vec<CameraRaw>> {
+    let error = |source: BoxError| cameras_error(path, source);
+    let text = fs::read_to_string(path).map_err(|e| error(e.into()))?;
+    let mut values = text
+        .lines()
+        .filter(|l| !l.trim_start().starts_with('#'))
+        .flat_map(|l| l.split_whitespace());
+    let cameras_num = match values.next().map(|v| v.parse::<usize>()) {
+        Some(Ok(n)) => n,
+        _ => return Err(error("expected the number of cameras".into())),
+    };
+    //Number of points
+    values.next();
+    let numbers = values
+        .take(cameras_num * CAMERA_VALUES)
+        .map(|v| {
+            v.parse::<f32>()
+                .map_err(|_| error(format!("expected a number, got '{}'", v).into()))
+        })
+        .collect::<Result<Vec<f32>>>()?;
+    if numbers.len() < cameras_num * CAMERA_VALUES {
+        return Err(error(
+            format!("expected {} cameras, file ends early", cameras_num).into(),
+        ));
+    }
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let path_list = dir.join("list.txt");
+    let list = fs::read_to_string(&path_list).map_err(|e| cameras_error(&path_list, e.into()))?;
+    let images: Vec<&str> = list
+        .lines()
+        .filter_map(|l| l.split_whitespace().next())
+        .collect();
+    if images.len() < cameras_num {
+        return Err(cameras_error(
+            &path_list,
+            format!("{} images for {} cameras", images.len(), cameras_num).into(),
+        ));
+    }
+
+    let mut cameras = Vec::<CameraRaw>::new();
+    for (c, image) in numbers.chunks_exact(CAMERA_VALUES).zip(images) {
+        let [focal, k1, k2] = [c[0], c[1], c[2]];
+        if focal == 0.0 {
+            continue;
+        }
+        //World to camera, camera looking down -Z with Y up as in eyek
+        let world_to_cam = Rotation3::from_matrix_unchecked(Matrix3::from_row_slice(&c[3..12]));
+        let translation = Vector3::new(c[12], c[13], c[14]);
+        let pos = world_to_cam.inverse_transform_vector(&-translation);
+        let rot = UnitQuaternion::from_rotation_matrix(&world_to_cam).inverse();
+
+        let image_path = dir.join(image).to_string_lossy().into_owned();
+        //Bundler has no image size
+        let (width, _) =
+            image::image_dimensions(&image_path).map_err(|e| EyekError::CameraImage {
+                path: (&image_path).into(),
+                source: e,
+            })?;
+        let distortion = match k1 == 0.0 && k2 == 0.0 {
+            true => None,
+            false => Some(Distortion::Brown {
+                k1,
+                k2,
+                k3: 0.0,
+                p1: 0.0,
+                p2: 0.0,
+            }),
+        };
+        let id = cameras.len();
+        cameras.push(CameraRaw {
+            id,
+            pos: [pos.x, pos.y, pos.z],
+            rot,
+            scale: [1.0, 1.0, 1.0],
+            camera_type: CameraType::Perspective,
+            fov_x: 2.0 * (width as f32 / (2.0 * focal)).atan(),
+            limit_near: DEFAULT_NEAR,
+            limit_far: DEFAULT_FAR,
+            name: image_stem(&image_path).unwrap_or_else(|| id.to_string()),
+            image_path,
+            mask_path: None,
+            shift: [0.0, 0.0],
+            principal_point: None,
+            sensor_fit: Some(SensorFit::Horizontal),
+            pixel_aspect: 1.0,
+            distortion,
+            weight: 1.0,
+            opacity: 1.0,
+            priority: 0,
+        });
+    }
+    Ok(cameras)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::test_util::{assert_known_pose, translation, TempDir, WORLD_TO_CAM};
+
+    #[test]
+    fn bundler_camera() {
+        let dir = TempDir::new("bundler");
+        let rows: Vec<String> = WORLD_TO_CAM
+            .iter()
+            .map(|row| format!("{} {} {}", row[0], row[1], row[2]))
+            .collect();
+        let [tx, ty, tz] = translation();
+        //The second camera was not reconstructed
+        let path = dir.write(
+            "bundle.out",
+            &format!(
+                "# Bundle file v0.3\n2 0\n32 0 0\n{}\n{} {} {}\n0 0 0\n1 0 0\n0 1 0\n0 0 1\n0 0 0\n",
+                rows.join("\n"),
+                tx,
+                ty,
+                tz
+            ),
+        );
+        dir.write("list.txt", "shot.png 0 32\nlost.png\n");
+        image::RgbImage::new(64, 48)
+            .save(dir.path().join("shot.png"))
+            .unwrap();
+        let cameras = load_bundler(&path).unwrap();
+        assert_eq!(cameras.len(), 1);
+        let cam = &cameras[0];
+        //Same axes as eyek, -Z forward and +Y up
+        assert_known_pose(cam.pos, cam.rot, [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]);
+        assert!((cam.fov_x - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert_eq!(cam.distortion, None);
+        assert_eq!(cam.name, "shot");
+    }
+}
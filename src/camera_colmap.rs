@@ -0,0 +1,270 @@
+use crate::camera::{
+    cameras_error, flip_opencv, image_stem, CameraRaw, CameraType, Distortion, SensorFit,
+    DEFAULT_FAR, DEFAULT_NEAR,
+};
+use crate::error::{BoxError, Result};
+use bvh::nalgebra::geometry::{Quaternion, UnitQuaternion};
+use bvh::nalgebra::{Vector3, Vector4};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+struct ColmapCamera {
+    width: f32,
+    focal: [f32; 2],
+    principal_point: [f32; 2],
+    distortion: Option<Distortion>,
+}
+
+// Lines without comments, keeping empty ones: images.txt has an empty
+// points line for images without observations.
+fn read_lines(path: &Path) -> Result<Vec<(usize, String)>> {
+    let text = fs::read_to_string(path).map_err(|e| cameras_error(path, e.into()))?;
+    Ok(text
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim_start().starts_with('#'))
+        .map(|(i, l)| (i + 1, l.trim().to_string()))
+        .collect())
+}
+
+fn parse_numbers(line: usize, values: &[&str]) -> std::result::Result<Vec<f32>, BoxError> {
+    values
+        .iter()
+        .map(|v| {
+            v.parse::<f32>()
+                .map_err(|_| format!("line {}: expected a number, got '{}'", line, v).into())
+        })
+        .collect()
+}
+
+fn parse_camera(line: usize, text: &str) -> std::result::Result<(u32, ColmapCamera), BoxError> {
+    let values: Vec<&str> = text.split_whitespace().collect();
+    if values.len() < 4 {
+        return Err(format!(
+            "line {}: expected CAMERA_ID MODEL WIDTH HEIGHT PARAMS",
+            line
+        )
+        .into());
+    }
+    let id = values[0]
+        .parse::<u32>()
+        .map_err(|_| format!("line {}: invalid camera id '{}'", line, values[0]))?;
+    let model = values[1];
+    let width = parse_numbers(line, &values[2..4])?[0];
+    let p = parse_numbers(line, &values[4..])?;
+    let expected = match model {
+        "SIMPLE_PINHOLE" => 3,
+        "PINHOLE" | "SIMPLE_RADIAL" => 4,
+        "RADIAL" => 5,
+        "OPENCV" | "OPENCV_FISHEYE" => 8,
+        _ => {
+            return Err(format!(
+                "line {}: unsupported camera model {}, expected one of: \
+                 SIMPLE_PINHOLE, PINHOLE, SIMPLE_RADIAL, RADIAL, OPENCV, OPENCV_FISHEYE",
+                line, model
+            )
+            .into())
+        }
+    };
+    if p.len() != expected {
+        return Err(format!(
+            "line {}: {} has {} parameters, got {}",
+            line,
+            model,
+            expected,
+            p.len()
+        )
+        .into());
+    }
+    let (focal, principal_point, distortion) = match model {
+        "SIMPLE_PINHOLE" => ([p[0], p[0]], [p[1], p[2]], None),
+        "PINHOLE" => ([p[0], p[1]], [p[2], p[3]], None),
+        "SIMPLE_RADIAL" | "RADIAL" => (
+            [p[0], p[0]],
+            [p[1], p[2]],
+            Some(Distortion::Brown {
+                k1: p[3],
+                k2: p.get(4).copied().unwrap_or(0.0),
+                k3: 0.0,
+                p1: 0.0,
+                p2: 0.0,
+            }),
+        ),
+        "OPENCV" => (
+            [p[0], p[1]],
+            [p[2], p[3]],
+            Some(Distortion::Brown {
+                k1: p[4],
+                k2: p[5],
+                k3: 0.0,
+                p1: p[6],
+                p2: p[7],
+            }),
+        ),
+        _ => (
+            [p[0], p[1]],
+            [p[2], p[3]],
+            Some(Distortion::Fisheye {
+                k1: p[4],
+                k2: p[5],
+                k3: p[6],
+                k4: p[7],
+            }),
+        ),
+    };
+    Ok((
+        id,
+        ColmapCamera {
+            width,
+            focal,
+            principal_point,
+            distortion,
+        },
+    ))
+}
+
+/// Loads a COLMAP sparse text model: `cameras.txt` and the `images.txt` next to it.
+/// Image names are relative to the photo directory of the reconstruction: `images`,
+/// otherwise `../../images` of the standard `images` + `sparse/0` layout if it exists,
+/// otherwise the model directory.
+pub fn load_colmap(path: &Path, images: Option<&Path>) -> Result<Vec<CameraRaw>> {
+    let mut colmap_cameras = HashMap::<u32, ColmapCamera>::new();
+    for (line, text) in read_lines(path)? {
+        if text.is_empty() {
+            continue;
+        }
+        let (id, camera) = parse_camera(line, &text).map_err(|e| cameras_error(path, e))?;
+        colmap_cameras.insert(id, camera);
+    }
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let standard_images = dir.join("..").join("..").join("images");
+    let image_dir = match images {
+        Some(images) => images.to_path_buf(),
+        None if standard_images.is_dir() => standard_images,
+        None => dir.to_path_buf(),
+    };
+    let path_images = dir.join("images.txt");
+    let images_error = |source: BoxError| cameras_error(&path_images, source);
+    let lines = read_lines(&path_images)?;
+    //Every image line is followed by a line of 2D points
+    let mut images = Vec::<(u32, CameraRaw)>::new();
+    for (line, text) in lines.iter().step_by(2) {
+        if text.is_empty() {
+            continue;
+        }
+        let values: Vec<&str> = text.split_whitespace().collect();
+        if values.len() < 10 {
+            return Err(images_error(
+                format!(
+                    "line {}: expected IMAGE_ID QW QX QY QZ TX TY TZ CAMERA_ID NAME",
+                    line
+                )
+                .into(),
+            ));
+        }
+        let image_id = values[0].parse::<u32>().map_err(|_| {
+            images_error(format!("line {}: invalid image id '{}'", line, values[0]).into())
+        })?;
+        let pose = parse_numbers(*line, &values[1..8]).map_err(images_error)?;
+        let camera_id = values[8].parse::<u32>().ok();
+        let camera = match camera_id.and_then(|id| colmap_cameras.get(&id)) {
+            Some(camera) => camera,
+            None => {
+                return Err(images_error(
+                    format!("line {}: unknown camera id '{}'", line, values[8]).into(),
+                ))
+            }
+        };
+        let name = values[9..].join(" ");
+
+        //World to camera, camera looking down +Z with Y down
+        let world_to_cam = UnitQuaternion::from_quaternion(Quaternion::from(Vector4::new(
+            pose[1], pose[2], pose[3], pose[0],
+        )));
+        let translation = Vector3::new(pose[4], pose[5], pose[6]);
+        let pos = world_to_cam.inverse_transform_vector(&-translation);
+        let rot = world_to_cam.inverse() * flip_opencv();
+
+        let [fx, fy] = camera.focal;
+        images.push((
+            image_id,
+            CameraRaw {
+                id: 0,
+                pos: [pos.x, pos.y, pos.z],
+                rot,
+                scale: [1.0, 1.0, 1.0],
+                camera_type: CameraType::Perspective,
+                fov_x: 2.0 * (camera.width / (2.0 * fx)).atan(),
+                limit_near: DEFAULT_NEAR,
+                limit_far: DEFAULT_FAR,
+                image_path: image_dir.join(&name).to_string_lossy().into_owned(),
+                mask_path: None,
+                name: image_stem(&name).unwrap_or(name),
+                shift: [0.0, 0.0],
+                principal_point: Some(camera.principal_point),
+                sensor_fit: Some(SensorFit::Horizontal),
+                pixel_aspect: fy / fx,
+                distortion: camera.distortion,
+                weight: 1.0,
+                opacity: 1.0,
+                priority: 0,
+            },
+        ));
+    }
+    images.sort_by_key(|(image_id, _)| *image_id);
+    Ok(images
+        .into_iter()
+        .enumerate()
+        .map(|(id, (_, camera))| CameraRaw { id, ..camera })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::test_util::{assert_known_pose, translation, world_to_cam, TempDir};
+
+    #[test]
+    fn colmap_camera() {
+        let dir = TempDir::new("colmap");
+        let path = dir.write(
+            "cameras.txt",
+            "# CAMERA_ID MODEL WIDTH HEIGHT PARAMS\n1 PINHOLE 640 480 500 400 330 240\n",
+        );
+        let q = UnitQuaternion::from_rotation_matrix(&world_to_cam());
+        let [tx, ty, tz] = translation();
+        dir.write(
+            "images.txt",
+            &format!(
+                "# IMAGE_ID QW QX QY QZ TX TY TZ CAMERA_ID NAME\n1 {} {} {} {} {} {} {} 1 shot 1.jpg\n\n",
+                q.w, q.i, q.j, q.k, tx, ty, tz
+            ),
+        );
+        let cameras = load_colmap(&path, Some(Path::new("photos"))).unwrap();
+        assert_eq!(cameras.len(), 1);
+        let cam = &cameras[0];
+        //OpenCV +Z forward and +Y down
+        assert_known_pose(cam.pos, cam.rot, [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]);
+        assert!((cam.fov_x - 2.0 * (0.64f32).atan()).abs() < 1e-6);
+        assert_eq!(cam.pixel_aspect, 0.8);
+        assert_eq!(cam.principal_point, Some([330.0, 240.0]));
+        assert_eq!(cam.name, "shot 1");
+        assert_eq!(
+            Path::new(&cam.image_path),
+            Path::new("photos").join("shot 1.jpg")
+        );
+    }
+
+    #[test]
+    fn colmap_camera_models() {
+        let (id, camera) = parse_camera(1, "7 SIMPLE_RADIAL 100 80 50 50 40 0.1").unwrap();
+        assert_eq!(id, 7);
+        assert_eq!(camera.focal, [50.0, 50.0]);
+        assert_eq!(camera.principal_point, [50.0, 40.0]);
+        assert!(matches!(camera.distortion, Some(Distortion::Brown { k1, .. }) if k1 == 0.1));
+        assert!(parse_camera(1, "1 PINHOLE 100 80 50 50 40").is_err());
+        assert!(parse_camera(1, "1 THIN_PRISM_FISHEYE 100 80 1 2 3 4").is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use crate::camera::{
+    cameras_error, flip_opencv, image_stem, CameraRaw, CameraType, Distortion, SensorFit,
+    DEFAULT_FAR, DEFAULT_NEAR,
+};
+use crate::error::{BoxError, Result};
+use bvh::nalgebra::geometry::{Rotation3, UnitQuaternion};
+use bvh::nalgebra::Matrix3;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// AliceVision writes every number as a string, other exporters don't.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Field {
+    Number(f64),
+    Text(String),
+}
+impl Field {
+    fn key(&self) -> String {
+        match self {
+            Field::Number(n) => n.to_string(),
+            Field::Text(s) => s.clone(),
+        }
+    }
+
+    fn value(&self) -> std::result::Result<f32, BoxError> {
+        match self {
+            Field::Number(n) => Ok(*n as f32),
+            Field::Text(s) => s
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| format!("expected a number, got '{}'", s).into()),
+        }
+    }
+}
+
+fn values(fields: &[Field]) -> std::result::Result<Vec<f32>, BoxError> {
+    fields.iter().map(Field::value).collect()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct View {
+    pose_id: Field,
+    intrinsic_id: Field,
+    path: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Intrinsic {
+    intrinsic_id: Field,
+    width: Field,
+    height: Field,
+    #[serde(rename = "type")]
+    kind: String,
+    distortion_type: Option<String>,
+    px_focal_length: Option<Field>,
+    focal_length: Option<Field>,
+    sensor_width: Option<Field>,
+    principal_point: [Field; 2],
+    #[serde(default)]
+    distortion_params: Vec<Field>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Transform {
+    rotation: [Field; 9],
+    center: [Field; 3],
+}
+#[derive(Debug, Deserialize)]
+struct Pose {
+    transform: Transform,
+}
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PoseEntry {
+    pose_id: Field,
+    pose: Pose,
+}
+
+#[derive(Debug, Deserialize)]
+struct SfmJSON {
+    #[serde(default)]
+    version: Vec<Field>,
+    #[serde(default)]
+    views: Vec<View>,
+    #[serde(default)]
+    intrinsics: Vec<Intrinsic>,
+    #[serde(default)]
+    poses: Vec<PoseEntry>,
+}
+
+struct MeshroomCamera {
+    width: f32,
+    focal: f32,
+    principal_point: [f32; 2],
+    distortion: Option<Distortion>,
+}
+
+fn parse_intrinsic(
+    intrinsic: &Intrinsic,
+    centered_principal_point: bool,
+) -> std::result::Result<MeshroomCamera, BoxError> {
+    let width = intrinsic.width.value()?;
+    let focal = match (
+        &intrinsic.px_focal_length,
+        &intrinsic.focal_length,
+        &intrinsic.sensor_width,
+    ) {
+        (Some(px), _, _) => px.value()?,
+        (None, Some(mm), Some(sensor)) => mm.value()? * width / sensor.value()?,
+        _ => return Err("intrinsic without pxFocalLength or focalLength and sensorWidth".into()),
+    };
+    let [cx, cy] = [
+        intrinsic.principal_point[0].value()?,
+        intrinsic.principal_point[1].value()?,
+    ];
+    //Since 1.2.0 the principal point is an offset from the image center
+    let principal_point = match centered_principal_point {
+        true => [width / 2.0 + cx, intrinsic.height.value()? / 2.0 + cy],
+        false => [cx, cy],
+    };
+    let k = values(&intrinsic.distortion_params)?;
+    let model = intrinsic
+        .distortion_type
+        .as_deref()
+        .unwrap_or(&intrinsic.kind);
+    let expected = match model {
+        "pinhole" => 0,
+        "radial1" => 1,
+        "radial3" => 3,
+        "brown" => 5,
+        "fisheye4" => 4,
+        _ => {
+            return Err(format!(
+                "unsupported camera type {}, expected one of: pinhole, radial1, radial3, brown, fisheye4",
+                model
+            )
+            .into())
+        }
+    };
+    if k.len() != expected {
+        return Err(format!(
+            "{} has {} distortion parameters, got {}",
+            model,
+            expected,
+            k.len()
+        )
+        .into());
+    }
+    let distortion = match model {
+        "radial1" | "radial3" | "brown" => Some(Distortion::Brown {
+            k1: k[0],
+            k2: k.get(1).copied().unwrap_or(0.0),
+            k3: k.get(2).copied().unwrap_or(0.0),
+            p1: k.get(3).copied().unwrap_or(0.0),
+            p2: k.get(4).copied().unwrap_or(0.0),
+        }),
+        "fisheye4" => Some(Distortion::Fisheye {
+            k1: k[0],
+            k2: k[1],
+            k3: k[2],
+            k4: k[3],
+        }),
+        _ => None,
+    };
+    Ok(MeshroomCamera {
+        width,
+        focal,
+        principal_point,
+        distortion,
+    })
+}
+
+/// Loads reconstructed views of a Meshroom / AliceVision `cameras.sfm`.
+/// Views without a pose are skipped, image paths are resolved against the file directory.
+pub fn load_meshroom(path: &Path) -> Result<Vec<CameraRaw>> {
+    let error = |source: BoxError| cameras_error(path, source);
+    let sfm: SfmJSON = fs::File::open(path)
+        .map_err(serde_json::Error::io)
+        .and_then(|file_json| serde_json::from_reader(io::BufReader::new(file_json)))
+        .map_err(|e| error(e.into()))?;
+    let version: Vec<u32> = sfm
+        .version
+        .iter()
+        .map(|v| v.value().map(|v| v as u32))
+        .collect::<std::result::Result<_, _>>()
+        .map_err(error)?;
+    let centered_principal_point = version >= vec![1, 2, 0];
+
+    let mut intrinsics = HashMap::<String, MeshroomCamera>::new();
+    for intrinsic in sfm.intrinsics.iter() {
+        let id = intrinsic.intrinsic_id.key();
+        let camera = parse_intrinsic(intrinsic, centered_principal_point)
+            .map_err(|e| error(format!("intrinsic {}: {}", id, e).into()))?;
+        intrinsics.insert(id, camera);
+    }
+    let mut poses = HashMap::<String, &Transform>::new();
+    for pose in sfm.poses.iter() {
+        poses.insert(pose.pose_id.key(), &pose.pose.transform);
+    }
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut cameras = Vec::<CameraRaw>::new();
+    for view in sfm.views.iter() {
+        let transform = match poses.get(&view.pose_id.key()) {
+            Some(transform) => transform,
+            None => continue,
+        };
+        let camera = match intrinsics.get(&view.intrinsic_id.key()) {
+            Some(camera) => camera,
+            None => {
+                return Err(error(
+                    format!("unknown intrinsic id '{}'", view.intrinsic_id.key()).into(),
+                ))
+            }
+        };
+        let r = values(&transform.rotation).map_err(error)?;
+        let center = values(&transform.center).map_err(error)?;
+        //Column-major world to camera rotation, read as rows of camera to world
+        let cam_to_world = Rotation3::from_matrix_unchecked(Matrix3::new(
+            r[0], r[1], r[2], r[3], r[4], r[5], r[6], r[7], r[8],
+        ));
+        let rot = UnitQuaternion::from_rotation_matrix(&cam_to_world) * flip_opencv();
+        let image_path = dir.join(&view.path).to_string_lossy().into_owned();
+        let id = cameras.len();
+        cameras.push(CameraRaw {
+            id,
+            pos: [center[0], center[1], center[2]],
+            rot,
+            scale: [1.0, 1.0, 1.0],
+            camera_type: CameraType::Perspective,
+            fov_x: 2.0 * (camera.width / (2.0 * camera.focal)).atan(),
+            limit_near: DEFAULT_NEAR,
+            limit_far: DEFAULT_FAR,
+            name: image_stem(&image_path).unwrap_or_else(|| id.to_string()),
+            image_path,
+            mask_path: None,
+            shift: [0.0, 0.0],
+            principal_point: Some(camera.principal_point),
+            sensor_fit: Some(SensorFit::Horizontal),
+            pixel_aspect: 1.0,
+            distortion: camera.distortion,
+            weight: 1.0,
+            opacity: 1.0,
+            priority: 0,
+        });
+    }
+    Ok(cameras)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::test_util::{assert_known_pose, TempDir, CENTER, WORLD_TO_CAM};
+    use serde_json::json;
+
+    #[test]
+    fn meshroom_camera() {
+        let dir = TempDir::new("meshroom");
+        //AliceVision strings, rotation stored column-major
+        let rotation: Vec<String> = (0..9)
+            .map(|i| WORLD_TO_CAM[i % 3][i / 3].to_string())
+            .collect();
+        let center = CENTER.map(|c| c.to_string());
+        let sfm = json!({
+            "version": ["1", "2", "0"],
+            "views": [
+                {"viewId": "10", "poseId": "20", "intrinsicId": "30", "path": "shot.jpg"},
+                {"viewId": "11", "poseId": "21", "intrinsicId": "30", "path": "lost.jpg"},
+            ],
+            "intrinsics": [{
+                "intrinsicId": "30", "width": "640", "height": "480", "type": "radial3",
+                "pxFocalLength": "500", "principalPoint": ["10", "-5"],
+                "distortionParams": ["0.1", "0", "0"],
+            }],
+            "poses": [{
+                "poseId": "20",
+                "pose": {"transform": {"rotation": rotation, "center": center}},
+            }],
+        });
+        let path = dir.write("cameras.sfm", &sfm.to_string());
+        let cameras = load_meshroom(&path).unwrap();
+        assert_eq!(cameras.len(), 1);
+        let cam = &cameras[0];
+        //OpenCV +Z forward and +Y down
+        assert_known_pose(cam.pos, cam.rot, [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]);
+        assert!((cam.fov_x - 2.0 * (0.64f32).atan()).abs() < 1e-6);
+        assert_eq!(cam.principal_point, Some([330.0, 235.0]));
+        assert!(matches!(cam.distortion, Some(Distortion::Brown { k1, .. }) if k1 == 0.1));
+        assert_eq!(cam.name, "shot");
+        assert_eq!(Path::new(&cam.image_path), dir.path().join("shot.jpg"));
+    }
+}
@@ -0,0 +1,315 @@
+use eyek::{
+    Blending, EyekError, Filter, JobJSON, Mipmap, Properties, Result, Supersampling, TileNaming,
+    UvChannel,
+};
+use std::path::Path;
+
+pub const USAGE: &str = "Usage:
+  eyek --data <dir> --out <path> [options]
+  eyek --job <job.json>
+  eyek <data> <out> <res_x> <res_y> <clip_uv> <blending> <backface_culling> <occlude> <bleed>
+
+Options:
+  --job <file>          Read every option from a job file. Can't be combined with other options.
+  --data <dir>          Directory with mesh.obj, mesh.glb or mesh.gltf and cameras.json
+                        or a COLMAP text model (cameras.txt and images.txt),
+                        Meshroom cameras.sfm or Bundler bundle.out with list.txt.
+                        The mesh goes next to the cameras: for COLMAP copy it into the
+                        model directory, e.g. project/sparse/0/mesh.obj.
+  --images <dir>        Photo directory of a COLMAP model. Default: project/images for a
+                        project/sparse/0 model if it exists, otherwise the model directory.
+  --out <path>          Texture path without extension, named by --naming.
+                        Or a template with tokens: <UDIM> (1001), <UVTILE> (u1_v1),
+                        <UVTILE0> (u0_v0), <U>, <V>, <U0>, <V0> and <camera>.
+                        With <camera> every camera is written to its own texture.
+  --naming <style>      auto (out.png for one tile, out.1001.png otherwise), mari (out.1001.png),
+                        mudbox (out_u1_v1.png) or uvtile (out_u0_v0.png). Default: auto.
+  --res <X>x<Y>         Texture resolution, e.g. 4096x4096. Default: 1024x1024.
+  --blend <method>      average, median, mode or overlay. Default: overlay.
+  --clip-uv             With a separate --uv-tile-set, don't repeat UVs outside of the 0..1 range.
+                        UVs of a tile are never repeated into other tiles.
+  --backface-culling    Ignore faces pointing away from the camera.
+  --occlude             Allow polygons to shade each other.
+  --bleed <pixels>      Extend paint beyond UV island bounds, 0..255. Default: 0.
+  --feather <pixels>    Fade projections out over this many source pixels from the image border.
+                        Default: 0.
+  --feather-angle <deg> Fade projections out over this many degrees before faces turn edge-on
+                        to the camera, 0..90. Default: 0.
+  --filter <filter>     Source photo filtering: nearest, bilinear, bicubic (Catmull-Rom)
+                        or lanczos. Default: nearest.
+  --supersampling <pattern>
+                        Rays cast per texel: corners, center, jittered (one per cell of an
+                        N×N grid) or rotated_grid (N×N). Default: corners.
+  --supersampling-grid <N>
+                        N for jittered and rotated_grid, 1..16. Default: 2.
+  --mipmap <mode>       Filter over the source area seen by each texel: off, trilinear or
+                        anisotropic. Smooths distant and grazing shots. Levels are read
+                        bilinearly when --filter is nearest. Default: off.
+  --uv-set <uv>         UV channel receiving the projection, by index or glTF attribute name.
+                        Default: 0.
+  --uv-tile-set <uv>    UV channel used for UDIM tiling. Default: same as --uv-set.
+  --keep-data           Don't delete the data directory after projection.
+                        Only directories marked with a .eyek file are ever deleted.
+  -h, --help            Print this message.";
+
+pub enum Command {
+    Run(Properties),
+    Help,
+}
+
+pub fn parse_arguments(args: Vec<String>) -> Result<Command> {
+    match args.get(1) {
+        Some(first) if first.starts_with('-') => parse_named(args.into_iter().skip(1)),
+        Some(_) => parse_positional(args).map(Command::Run),
+        None => Ok(Command::Help),
+    }
+}
+
+fn parse_named(mut args: impl Iterator<Item = String>) -> Result<Command> {
+    let mut job = None;
+    let mut others = false;
+    let mut path_data = None;
+    let mut path_texture = None;
+    let mut properties = Properties::default();
+
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let mut value = || match inline.clone().or_else(|| args.next()) {
+            Some(value) => Ok(value),
+            None => Err(EyekError::invalid_argument(&flag, "missing value")),
+        };
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--job" => {
+                job = Some(value()?);
+                continue;
+            }
+            "--data" => path_data = Some(value()?),
+            "--out" => path_texture = Some(value()?),
+            "--images" => properties.path_images = Some(value()?),
+            "--res" => (properties.img_res_x, properties.img_res_y) = parse_res(&flag, &value()?)?,
+            "--blend" => properties.blending = parse_blend(&flag, &value()?)?,
+            "--bleed" => properties.bleed = parse_bleed(&flag, &value()?)?,
+            "--naming" => properties.naming = parse_naming(&flag, &value()?)?,
+            "--feather" => properties.feather = parse_feather(&flag, &value()?)?,
+            "--feather-angle" => properties.feather_angle = parse_feather_angle(&flag, &value()?)?,
+            "--filter" => properties.filter = parse_filter(&flag, &value()?)?,
+            "--supersampling" => properties.supersampling = parse_supersampling(&flag, &value()?)?,
+            "--supersampling-grid" => properties.supersampling_grid = parse_grid(&flag, &value()?)?,
+            "--mipmap" => properties.mipmap = parse_mipmap(&flag, &value()?)?,
+            "--uv-set" => properties.uv_set = UvChannel::parse(&value()?),
+            "--uv-tile-set" => properties.uv_tile_set = Some(UvChannel::parse(&value()?)),
+            "--clip-uv" | "--backface-culling" | "--occlude" | "--keep-data" => {
+                let on = match &inline {
+                    Some(v) => parse_bool(&flag, v)?,
+                    None => true,
+                };
+                match flag.as_str() {
+                    "--clip-uv" => properties.clip_uv = on,
+                    "--backface-culling" => properties.backface_culling = on,
+                    "--keep-data" => properties.keep_data = on,
+                    _ => properties.occlude = on,
+                }
+            }
+            _ => {
+                return Err(EyekError::invalid_argument(
+                    &flag,
+                    "unknown option, see --help",
+                ))
+            }
+        }
+        others = true;
+    }
+
+    if let Some(job) = job {
+        if others {
+            return Err(EyekError::invalid_argument(
+                "--job",
+                "can't be combined with other options",
+            ));
+        }
+        return Ok(Command::Run(JobJSON::load(Path::new(&job))?.properties()));
+    }
+
+    let path_data =
+        path_data.ok_or_else(|| EyekError::invalid_argument("--data", "is required"))?;
+    let path_texture =
+        path_texture.ok_or_else(|| EyekError::invalid_argument("--out", "is required"))?;
+    Ok(Command::Run(Properties {
+        path_data,
+        path_texture,
+        ..properties
+    }))
+}
+
+// Old form used by the Blender add-on, kept for compatibility.
+// Trailing arguments after bleed (upscale) are ignored.
+fn parse_positional(args: Vec<String>) -> Result<Properties> {
+    if args.len() < 10 {
+        return Err(EyekError::invalid_argument(
+            "arguments",
+            format!(
+                "expected 9 positional arguments, got {}. See --help",
+                args.len() - 1
+            ),
+        ));
+    }
+
+    let properties = Properties {
+        path_data: args[1].to_string(),
+        path_texture: args[2].to_string(),
+        img_res_x: parse_dimension("res_x", &args[3])?,
+        img_res_y: parse_dimension("res_y", &args[4])?,
+        clip_uv: parse_bool("clip_uv", &args[5])?,
+        blending: match args[6].as_str() {
+            "0" => Blending::Average,
+            "1" => Blending::Median,
+            "2" => Blending::Mode,
+            "3" => Blending::Overlay,
+            other => parse_blend("blending", other)?,
+        },
+        backface_culling: parse_bool("backface_culling", &args[7])?,
+        occlude: parse_bool("occlude", &args[8])?,
+        bleed: parse_bleed("bleed", &args[9])?,
+        ..Default::default()
+    };
+
+    Ok(properties)
+}
+
+fn parse_dimension(name: &str, value: &str) -> Result<u32> {
+    match value.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(EyekError::invalid_argument(
+            name,
+            format!("expected a positive integer, got '{}'", value),
+        )),
+    }
+}
+
+fn parse_res(name: &str, value: &str) -> Result<(u32, u32)> {
+    match value.split_once(['x', 'X']) {
+        Some((x, y)) => Ok((parse_dimension(name, x)?, parse_dimension(name, y)?)),
+        None => {
+            let n = parse_dimension(name, value)?;
+            Ok((n, n))
+        }
+    }
+}
+
+fn parse_blend(name: &str, value: &str) -> Result<Blending> {
+    Blending::from_name(value).ok_or_else(|| {
+        EyekError::invalid_argument(
+            name,
+            format!(
+                "unknown blending '{}', expected one of: {}",
+                value,
+                Blending::NAMES.join(", ")
+            ),
+        )
+    })
+}
+
+fn parse_naming(name: &str, value: &str) -> Result<TileNaming> {
+    TileNaming::from_name(value).ok_or_else(|| {
+        EyekError::invalid_argument(
+            name,
+            format!(
+                "unknown naming '{}', expected one of: {}",
+                value,
+                TileNaming::NAMES.join(", ")
+            ),
+        )
+    })
+}
+
+fn parse_filter(name: &str, value: &str) -> Result<Filter> {
+    Filter::from_name(value).ok_or_else(|| {
+        EyekError::invalid_argument(
+            name,
+            format!(
+                "unknown filter '{}', expected one of: {}",
+                value,
+                Filter::NAMES.join(", ")
+            ),
+        )
+    })
+}
+
+fn parse_supersampling(name: &str, value: &str) -> Result<Supersampling> {
+    Supersampling::from_name(value).ok_or_else(|| {
+        EyekError::invalid_argument(
+            name,
+            format!(
+                "unknown supersampling '{}', expected one of: {}",
+                value,
+                Supersampling::NAMES.join(", ")
+            ),
+        )
+    })
+}
+
+fn parse_mipmap(name: &str, value: &str) -> Result<Mipmap> {
+    Mipmap::from_name(value).ok_or_else(|| {
+        EyekError::invalid_argument(
+            name,
+            format!(
+                "unknown mipmap '{}', expected one of: {}",
+                value,
+                Mipmap::NAMES.join(", ")
+            ),
+        )
+    })
+}
+
+fn parse_grid(name: &str, value: &str) -> Result<u32> {
+    match value.parse::<u32>() {
+        Ok(n) if (1..=16).contains(&n) => Ok(n),
+        _ => Err(EyekError::invalid_argument(
+            name,
+            format!("expected an integer in 1..16, got '{}'", value),
+        )),
+    }
+}
+
+fn parse_bleed(name: &str, value: &str) -> Result<u8> {
+    value.parse::<u8>().map_err(|_| {
+        EyekError::invalid_argument(
+            name,
+            format!("expected an integer in 0..255, got '{}'", value),
+        )
+    })
+}
+
+fn parse_feather(name: &str, value: &str) -> Result<u32> {
+    value.parse::<u32>().map_err(|_| {
+        EyekError::invalid_argument(
+            name,
+            format!("expected a non-negative integer, got '{}'", value),
+        )
+    })
+}
+
+fn parse_feather_angle(name: &str, value: &str) -> Result<f32> {
+    match value.parse::<f32>() {
+        Ok(a) if (0.0..=90.0).contains(&a) => Ok(a),
+        _ => Err(EyekError::invalid_argument(
+            name,
+            format!("expected degrees in 0..90, got '{}'", value),
+        )),
+    }
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(EyekError::invalid_argument(
+            name,
+            format!("expected 0 or 1, got '{}'", value),
+        )),
+    }
+}
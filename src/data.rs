@@ -0,0 +1,62 @@
+use crate::error::{EyekError, Result};
+use std::fs;
+use std::path::Path;
+
+/// File the add-on puts into every data directory it creates for eyek.
+/// Only directories with this marker are ever deleted.
+pub const DATA_MARKER: &str = ".eyek";
+/// Files eyek may delete with a marked data directory. Job files aren't among them,
+/// a directory holding one is kept.
+pub const DATA_FILES: [&str; 13] = [
+    DATA_MARKER,
+    "mesh.obj",
+    "mesh.mtl",
+    "mesh.glb",
+    "mesh.gltf",
+    "mesh.bin",
+    "cameras.json",
+    "cameras.txt",
+    "images.txt",
+    "points3D.txt",
+    "cameras.sfm",
+    "bundle.out",
+    "list.txt",
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DataCleanup {
+    Removed,
+    Kept,
+    NoMarker,
+    Unexpected(Vec<String>),
+}
+
+/// Deletes the data directory if it is owned by eyek and holds nothing but eyek files.
+pub fn cleanup_data_dir(path_data: &str, keep_data: bool) -> Result<DataCleanup> {
+    if keep_data {
+        return Ok(DataCleanup::Kept);
+    }
+    let path = Path::new(path_data);
+    if !path.join(DATA_MARKER).is_file() {
+        return Ok(DataCleanup::NoMarker);
+    }
+    let write_error = |source| EyekError::Write {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut unexpected = Vec::new();
+    for entry in fs::read_dir(path).map_err(write_error)? {
+        let entry = entry.map_err(write_error)?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_file = entry.file_type().map_err(write_error)?.is_file();
+        if !is_file || !DATA_FILES.contains(&name.as_str()) {
+            unexpected.push(name);
+        }
+    }
+    if !unexpected.is_empty() {
+        unexpected.sort();
+        return Ok(DataCleanup::Unexpected(unexpected));
+    }
+    fs::remove_dir_all(path).map_err(write_error)?;
+    Ok(DataCleanup::Removed)
+}
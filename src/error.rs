@@ -0,0 +1,115 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+pub type BoxError = Box<dyn Error + Send + Sync>;
+pub type Result<T> = std::result::Result<T, EyekError>;
+
+#[derive(Debug)]
+pub enum EyekError {
+    InvalidArgument {
+        name: String,
+        reason: String,
+    },
+    Mesh {
+        path: PathBuf,
+        source: BoxError,
+    },
+    Cameras {
+        path: PathBuf,
+        source: BoxError,
+    },
+    CameraImage {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    Write {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Job {
+        path: PathBuf,
+        source: BoxError,
+    },
+}
+
+impl EyekError {
+    pub fn invalid_argument(name: &str, reason: impl Into<String>) -> Self {
+        EyekError::InvalidArgument {
+            name: name.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Short stable identifier of the error class, for scripts and the add-on.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EyekError::InvalidArgument { .. } => "invalid_argument",
+            EyekError::Mesh { .. } => "mesh",
+            EyekError::Cameras { .. } => "cameras",
+            EyekError::CameraImage { .. } => "camera_image",
+            EyekError::Write { .. } => "write",
+            EyekError::Job { .. } => "job",
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            EyekError::InvalidArgument { .. } => 2,
+            EyekError::Mesh { .. } => 3,
+            EyekError::Cameras { .. } => 4,
+            EyekError::CameraImage { .. } => 5,
+            EyekError::Write { .. } => 6,
+            EyekError::Job { .. } => 7,
+        }
+    }
+
+    /// One line of JSON: `{"error":"<kind>","code":<exit code>,"message":"..."}`.
+    pub fn to_json_line(&self) -> String {
+        serde_json::json!({
+            "error": self.kind(),
+            "code": self.exit_code(),
+            "message": self.to_string(),
+        })
+        .to_string()
+    }
+}
+
+impl fmt::Display for EyekError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EyekError::InvalidArgument { name, reason } => {
+                write!(f, "Invalid argument '{}': {}", name, reason)
+            }
+            EyekError::Mesh { path, source } => {
+                write!(f, "Can't load mesh {}: {}", path.display(), source)
+            }
+            EyekError::Cameras { path, source } => {
+                write!(f, "Can't load cameras {}: {}", path.display(), source)
+            }
+            EyekError::CameraImage { path, source } => {
+                write!(f, "Can't read camera image {}: {}", path.display(), source)
+            }
+            EyekError::Write { path, source } => {
+                write!(f, "Can't write {}: {}", path.display(), source)
+            }
+            EyekError::Job { path, source } => {
+                write!(f, "Invalid job {}: {}", path.display(), source)
+            }
+        }
+    }
+}
+
+impl Error for EyekError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EyekError::InvalidArgument { .. } => None,
+            EyekError::Mesh { source, .. } => Some(source.as_ref()),
+            EyekError::Cameras { source, .. } => Some(source.as_ref()),
+            EyekError::CameraImage { source, .. } => Some(source),
+            EyekError::Write { source, .. } => Some(source),
+            EyekError::Job { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use crate::error::{BoxError, EyekError, Result};
+use crate::{Blending, Filter, Mipmap, Properties, Supersampling, TileNaming, UvChannel};
+use serde_derive::Deserialize;
+use std::fs;
+use std::path::Path;
+
+pub const JOB_VERSION: u32 = 1;
+
+fn default_res() -> [u32; 2] {
+    let defaults = Properties::default();
+    [defaults.img_res_x, defaults.img_res_y]
+}
+fn default_blending() -> Blending {
+    Properties::default().blending
+}
+fn default_supersampling_grid() -> u32 {
+    Properties::default().supersampling_grid
+}
+
+/// Declarative description of a projection, read from `job.json`.
+/// Relative `data`, `out` and `images` paths are resolved against the job file directory.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobJSON {
+    pub version: u32,
+    pub data: String,
+    pub out: String,
+    #[serde(default)]
+    pub images: Option<String>,
+    #[serde(default = "default_res")]
+    pub res: [u32; 2],
+    #[serde(default)]
+    pub clip_uv: bool,
+    #[serde(default = "default_blending")]
+    pub blending: Blending,
+    #[serde(default)]
+    pub backface_culling: bool,
+    #[serde(default)]
+    pub occlude: bool,
+    #[serde(default)]
+    pub bleed: u8,
+    #[serde(default)]
+    pub keep_data: bool,
+    #[serde(default)]
+    pub uv_set: UvChannel,
+    #[serde(default)]
+    pub uv_tile_set: Option<UvChannel>,
+    #[serde(default)]
+    pub naming: TileNaming,
+    #[serde(default)]
+    pub feather: u32,
+    #[serde(default)]
+    pub feather_angle: f32,
+    #[serde(default)]
+    pub filter: Filter,
+    #[serde(default)]
+    pub supersampling: Supersampling,
+    #[serde(default = "default_supersampling_grid")]
+    pub supersampling_grid: u32,
+    #[serde(default)]
+    pub mipmap: Mipmap,
+    #[serde(default)]
+    pub comment: String,
+}
+
+impl JobJSON {
+    pub fn load(path: &Path) -> Result<Self> {
+        let job_error = |source: BoxError| EyekError::Job {
+            path: path.to_path_buf(),
+            source,
+        };
+        let text = fs::read_to_string(path).map_err(|e| job_error(e.into()))?;
+        let value: serde_json::Value =
+            serde_json::from_str(&text).map_err(|e| job_error(e.into()))?;
+        let fields = match value.as_object() {
+            Some(fields) => fields,
+            None => return Err(job_error("expected a JSON object".into())),
+        };
+        match fields.get("version").and_then(|v| v.as_u64()) {
+            Some(v) if v >= 1 && v <= JOB_VERSION as u64 => (),
+            Some(v) => {
+                return Err(job_error(
+                    format!("unsupported version {}, expected {}", v, JOB_VERSION).into(),
+                ))
+            }
+            None => return Err(job_error("missing integer \"version\"".into())),
+        }
+        //Unknown keys are rejected here
+        let job: JobJSON = serde_json::from_value(value).map_err(|e| job_error(e.into()))?;
+        if job.res[0] == 0 || job.res[1] == 0 {
+            return Err(job_error("\"res\" must be positive".into()));
+        }
+        if !(1..=16).contains(&job.supersampling_grid) {
+            return Err(job_error("\"supersampling_grid\" must be in 1..16".into()));
+        }
+        if !(0.0..=90.0).contains(&job.feather_angle) {
+            return Err(job_error("\"feather_angle\" must be in 0..90".into()));
+        }
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        Ok(JobJSON {
+            data: base.join(&job.data).to_string_lossy().into_owned(),
+            out: base.join(&job.out).to_string_lossy().into_owned(),
+            images: job
+                .images
+                .map(|images| base.join(images).to_string_lossy().into_owned()),
+            ..job
+        })
+    }
+
+    pub fn properties(self) -> Properties {
+        Properties {
+            path_data: self.data,
+            path_texture: self.out,
+            path_images: self.images,
+            img_res_x: self.res[0],
+            img_res_y: self.res[1],
+            clip_uv: self.clip_uv,
+            blending: self.blending,
+            backface_culling: self.backface_culling,
+            occlude: self.occlude,
+            bleed: self.bleed,
+            keep_data: self.keep_data,
+            uv_set: self.uv_set,
+            uv_tile_set: self.uv_tile_set,
+            naming: self.naming,
+            feather: self.feather,
+            feather_angle: self.feather_angle,
+            filter: self.filter,
+            supersampling: self.supersampling,
+            supersampling_grid: self.supersampling_grid,
+            mipmap: self.mipmap,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::test_util::TempDir;
+    use std::path::PathBuf;
+
+    fn load(dir: &TempDir, job: &str) -> Result<JobJSON> {
+        JobJSON::load(&dir.write("job.json", job))
+    }
+
+    fn error(result: Result<JobJSON>) -> String {
+        match result {
+            Err(EyekError::Job { source, .. }) => source.to_string(),
+            other => panic!("expected a job error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn job_version() {
+        let dir = TempDir::new("job_version");
+        let missing = error(load(&dir, r#"{"data": "d", "out": "o"}"#));
+        assert!(
+            missing.contains("missing integer \"version\""),
+            "{}",
+            missing
+        );
+        let newer = error(load(&dir, r#"{"version": 2, "data": "d", "out": "o"}"#));
+        assert!(newer.contains("unsupported version 2"), "{}", newer);
+        let zero = error(load(&dir, r#"{"version": 0, "data": "d", "out": "o"}"#));
+        assert!(zero.contains("unsupported version 0"), "{}", zero);
+        let text = error(load(&dir, r#"{"version": "1", "data": "d", "out": "o"}"#));
+        assert!(text.contains("version"), "{}", text);
+    }
+
+    #[test]
+    fn job_unknown_keys() {
+        let dir = TempDir::new("job_unknown");
+        let unknown = error(load(
+            &dir,
+            r#"{"version": 1, "data": "d", "out": "o", "resolution": [64, 64]}"#,
+        ));
+        assert!(
+            unknown.contains("unknown field `resolution`"),
+            "{}",
+            unknown
+        );
+    }
+
+    #[test]
+    fn job_res() {
+        let dir = TempDir::new("job_res");
+        let zero = error(load(
+            &dir,
+            r#"{"version": 1, "data": "d", "out": "o", "res": [0, 64]}"#,
+        ));
+        assert!(zero.contains("\"res\" must be positive"), "{}", zero);
+        let job = load(&dir, r#"{"version": 1, "data": "d", "out": "o"}"#).unwrap();
+        let defaults = Properties::default();
+        assert_eq!(job.res, [defaults.img_res_x, defaults.img_res_y]);
+    }
+
+    #[test]
+    fn job_relative_paths() {
+        let dir = TempDir::new("job_paths");
+        let job = load(
+            &dir,
+            r#"{"version": 1, "data": "data", "out": "tex/out", "images": "../photos"}"#,
+        )
+        .unwrap();
+        assert_eq!(Path::new(&job.data), dir.path().join("data"));
+        assert_eq!(Path::new(&job.out), dir.path().join("tex/out"));
+        assert_eq!(
+            job.images.map(PathBuf::from),
+            Some(dir.path().join("../photos"))
+        );
+        let absolute = dir.path().join("abs");
+        let job = load(
+            &dir,
+            &serde_json::json!({"version": 1, "data": absolute, "out": "o"}).to_string(),
+        )
+        .unwrap();
+        assert_eq!(Path::new(&job.data), absolute);
+        assert_eq!(job.images, None);
+    }
+}
@@ -0,0 +1,185 @@
+pub mod blending;
+pub mod camera;
+pub mod camera_bundler;
+pub mod camera_colmap;
+pub mod camera_meshroom;
+pub mod data;
+pub mod error;
+pub mod job;
+pub mod mesh;
+pub mod mesh_gltf;
+pub mod naming;
+pub mod projection;
+pub mod sampling;
+
+pub use blending::{combine_layers, expand_pixels, Blending, Color, Layer};
+pub use camera::{
+    expand_keys, frame_path, load_camera_file, load_cameras, load_cameras_json, CameraJSON,
+    CameraRaw, CameraType, Coords, Distortion, EulerOrder, UpAxis, VecCameraJSON, CAMERA_FILES,
+};
+pub use camera_bundler::load_bundler;
+pub use camera_colmap::load_colmap;
+pub use camera_meshroom::load_meshroom;
+pub use data::{cleanup_data_dir, DataCleanup, DATA_MARKER};
+pub use error::{EyekError, Result};
+pub use image::RgbaImage;
+pub use job::{JobJSON, JOB_VERSION};
+pub use mesh::{
+    group_udims, load_mesh_file, load_meshes, load_obj, tile_udim, udim_tile, uv_tiles, uv_udim,
+    Mesh, Tris3D, UDIMs, UvChannel, MESH_FILES, UDIM_FIRST, UDIM_LAST,
+};
+pub use mesh_gltf::load_gltf;
+pub use naming::{texture_path, TileNaming};
+pub use projection::{cast_pixels_rays, FisheyeLens, Projection};
+pub use sampling::{sample, Filter, Mipmap, Pyramid, Supersampling};
+
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+
+/// Options of one projection run. Fields added later get defaults, build with
+/// `Properties { path_data, path_texture, ..Default::default() }`.
+pub struct Properties {
+    pub path_data: String,
+    pub path_texture: String,
+    /// Directory of the photos named by a COLMAP model.
+    pub path_images: Option<String>,
+    pub img_res_x: u32,
+    pub img_res_y: u32,
+    pub clip_uv: bool,
+    pub blending: Blending,
+    pub backface_culling: bool,
+    pub occlude: bool,
+    pub bleed: u8,
+    pub keep_data: bool,
+    pub uv_set: UvChannel,
+    pub uv_tile_set: Option<UvChannel>,
+    pub naming: TileNaming,
+    /// Alpha falloff in source pixels from the image border, 0 is off.
+    pub feather: u32,
+    /// Alpha falloff in degrees before a face turns edge-on to the camera, 0 is off.
+    pub feather_angle: f32,
+    /// Filter reading source photos between their pixels.
+    pub filter: Filter,
+    /// Pattern of rays cast inside every texel.
+    pub supersampling: Supersampling,
+    /// Samples per texel side for jittered and rotated grid patterns.
+    pub supersampling_grid: u32,
+    /// Mip lookup matching the source footprint of every sample.
+    pub mipmap: Mipmap,
+    // upscale: u8,
+}
+impl Default for Properties {
+    fn default() -> Self {
+        Properties {
+            path_data: String::new(),
+            path_texture: String::new(),
+            path_images: None,
+            img_res_x: 1024,
+            img_res_y: 1024,
+            clip_uv: false,
+            blending: Blending::Overlay,
+            backface_culling: false,
+            occlude: false,
+            bleed: 0,
+            keep_data: false,
+            uv_set: UvChannel::default(),
+            uv_tile_set: None,
+            naming: TileNaming::default(),
+            feather: 0,
+            feather_angle: 0.0,
+            filter: Filter::default(),
+            supersampling: Supersampling::default(),
+            supersampling_grid: 2,
+            mipmap: Mipmap::default(),
+        }
+    }
+}
+
+/// Projects every camera onto the faces of one UDIM tile separately.
+/// Layers are ordered by camera priority, then id.
+pub fn project_udim_layers(
+    mesh: &Mesh,
+    udim: u32,
+    cameras: &[CameraRaw],
+    properties: &Properties,
+) -> Result<Vec<Layer>> {
+    let faces = match mesh.udims.get(&udim) {
+        Some(faces) => faces,
+        None => return Ok(vec![]),
+    };
+    //Painted UVs are local to the tile when tiles come from the same UVs
+    let tile = match &properties.uv_tile_set {
+        Some(uv_tile_set) if uv_tile_set != &properties.uv_set => None,
+        _ => Some(udim_tile(udim)),
+    };
+    //Parallel execution
+    let mut layers: Vec<(i32, Layer)> = cameras
+        .to_owned()
+        .into_par_iter()
+        .map(|cam| {
+            let mut texture = RgbaImage::new(properties.img_res_x, properties.img_res_y);
+            let (id, priority, weight, opacity) = (cam.id, cam.priority, cam.weight, cam.opacity);
+            cast_pixels_rays(
+                &mesh.tris,
+                cam,
+                faces,
+                &mesh.bvh,
+                &mut texture,
+                tile,
+                properties,
+            )?;
+            Ok((
+                priority,
+                Layer {
+                    id,
+                    weight,
+                    opacity,
+                    texture,
+                },
+            ))
+        })
+        .collect::<Result<_>>()?;
+    layers.sort_by_key(|(priority, layer)| (*priority, layer.id));
+    Ok(layers.into_iter().map(|(_, layer)| layer).collect())
+}
+
+/// Projects all cameras onto the faces of one UDIM tile.
+/// Returns `None` when no camera painted a single texel.
+pub fn project_udim(
+    mesh: &Mesh,
+    udim: u32,
+    cameras: &[CameraRaw],
+    properties: &Properties,
+) -> Result<Option<RgbaImage>> {
+    let textures = project_udim_layers(mesh, udim, cameras, properties)?;
+    if textures.is_empty() {
+        return Ok(None);
+    }
+
+    //Combining images
+    let (mut mono_texture, texture_is_empty) = combine_layers(textures, &properties.blending);
+    if texture_is_empty {
+        return Ok(None);
+    }
+
+    //Color empty pixels around polygons edges
+    for _ in 0..properties.bleed {
+        expand_pixels(&mut mono_texture, 0);
+    }
+    Ok(Some(mono_texture))
+}
+
+/// Projects all cameras onto every UDIM tile of the mesh, skipping empty tiles.
+pub fn project(
+    mesh: &Mesh,
+    cameras: &[CameraRaw],
+    properties: &Properties,
+) -> Result<BTreeMap<u32, RgbaImage>> {
+    let mut textures = BTreeMap::new();
+    for &udim in mesh.udims.keys() {
+        if let Some(texture) = project_udim(mesh, udim, cameras, properties)? {
+            textures.insert(udim, texture);
+        }
+    }
+    Ok(textures)
+}
@@ -0,0 +1,530 @@
+use crate::error::{EyekError, Result};
+use crate::mesh_gltf::load_gltf;
+use bvh::aabb::{Bounded, AABB};
+use bvh::bounding_hierarchy::BHShape;
+use bvh::bvh::BVH;
+use bvh::nalgebra::Point3;
+use serde_derive::Deserialize;
+use std::collections::{hash_map::Entry, HashMap};
+use std::fmt;
+use std::path::Path;
+use triangle::{Point, Triangle};
+
+pub(crate) fn point3_to_point(pt: Point3<f32>) -> Point<f32> {
+    Point {
+        x: pt.x,
+        y: pt.y,
+        z: pt.z,
+    }
+}
+pub(crate) fn point_to_point3(pt: Point<f32>) -> Point3<f32> {
+    Point3::new(pt.x, pt.y, pt.z)
+}
+
+/// UV layer picked by index (`TEXCOORD_<n>` in glTF) or by attribute name.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum UvChannel {
+    Index(u32),
+    Name(String),
+}
+impl Default for UvChannel {
+    fn default() -> Self {
+        UvChannel::Index(0)
+    }
+}
+impl UvChannel {
+    pub fn parse(value: &str) -> Self {
+        match value.parse::<u32>() {
+            Ok(index) => UvChannel::Index(index),
+            Err(_) => UvChannel::Name(value.to_string()),
+        }
+    }
+
+    /// Index of the channel, also for names in the `TEXCOORD_<n>` form.
+    pub fn index(&self) -> Option<u32> {
+        match self {
+            UvChannel::Index(index) => Some(*index),
+            UvChannel::Name(name) => name.strip_prefix("TEXCOORD_")?.parse().ok(),
+        }
+    }
+}
+impl fmt::Display for UvChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UvChannel::Index(index) => write!(f, "{}", index),
+            UvChannel::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+pub const UDIM_FIRST: u32 = 1001;
+pub const UDIM_LAST: u32 = 1100;
+
+/// UDIM number of the tile with integer coordinates `(u, v)`, `None` outside of 1001-1100.
+pub fn tile_udim(u: i32, v: i32) -> Option<u32> {
+    match (0..10).contains(&u) && (0..10).contains(&v) {
+        true => Some(UDIM_FIRST + u as u32 + 10 * v as u32),
+        false => None,
+    }
+}
+
+/// Integer coordinates `(u, v)` of the UDIM tile.
+pub fn udim_tile(udim: u32) -> (i32, i32) {
+    let tile = udim.saturating_sub(UDIM_FIRST) as i32;
+    (tile % 10, tile / 10)
+}
+
+/// UDIM number of the tile containing the UV point.
+pub fn uv_udim(u: f32, v: f32) -> Option<u32> {
+    tile_udim(u.floor() as i32, v.floor() as i32)
+}
+
+// Separating axis test: whether the triangle and the tile share any area.
+// Touching only along an edge or at a corner doesn't count.
+fn tris_overlaps_tile(tris: &Triangle<f32>, u: i32, v: i32) -> bool {
+    let pts = [tris.a, tris.b, tris.c];
+    let corners = [
+        [u as f32, v as f32],
+        [u as f32 + 1.0, v as f32],
+        [u as f32 + 1.0, v as f32 + 1.0],
+        [u as f32, v as f32 + 1.0],
+    ];
+    let mut axes = vec![[1.0, 0.0], [0.0, 1.0]];
+    for i in 0..3 {
+        let (p, q) = (pts[i], pts[(i + 1) % 3]);
+        axes.push([q.y - p.y, p.x - q.x]);
+    }
+    axes.iter().all(|axis| {
+        let project = |x: f32, y: f32| x * axis[0] + y * axis[1];
+        let (t_min, t_max) = pts.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| {
+            let d = project(p.x, p.y);
+            (lo.min(d), hi.max(d))
+        });
+        let (c_min, c_max) = corners.iter().fold((f32::MAX, f32::MIN), |(lo, hi), c| {
+            let d = project(c[0], c[1]);
+            (lo.min(d), hi.max(d))
+        });
+        t_min < c_max && c_min < t_max
+    })
+}
+
+/// Integer coordinates of every tile the UV triangle overlaps, in or out of the UDIM range.
+pub fn uv_tiles(tris: &Triangle<f32>) -> Vec<(i32, i32)> {
+    let [min, max] = tris.aabb();
+    let u_min = min.x.floor() as i32;
+    let v_min = min.y.floor() as i32;
+    let u_max = (max.x.ceil() as i32 - 1).max(u_min);
+    let v_max = (max.y.ceil() as i32 - 1).max(v_min);
+    let mut tiles = Vec::new();
+    for v in v_min..=v_max {
+        for u in u_min..=u_max {
+            if tris_overlaps_tile(tris, u, v) {
+                tiles.push((u, v));
+            }
+        }
+    }
+    tiles
+}
+
+#[derive(Debug, Clone)]
+pub struct Tris3D {
+    pub v_3d: Triangle<f32>,
+    pub v_uv: Triangle<f32>,
+    pub v_tile: Triangle<f32>,
+    pub min: Point<f32>,
+    pub max: Point<f32>,
+    pub node_index: usize,
+    pub id: usize,
+}
+impl Tris3D {
+    pub fn new(v_3d: Triangle<f32>, v_uv: Triangle<f32>, id: usize) -> Self {
+        let [a, b, c] = [v_3d.a, v_3d.b, v_3d.c];
+        Tris3D {
+            v_3d,
+            v_uv,
+            v_tile: v_uv,
+            min: Point {
+                x: a.x.min(b.x).min(c.x),
+                y: a.y.min(b.y).min(c.y),
+                z: a.z.min(b.z).min(c.z),
+            },
+            max: Point {
+                x: a.x.max(b.x).max(c.x),
+                y: a.y.max(b.y).max(c.y),
+                z: a.z.max(b.z).max(c.z),
+            },
+            node_index: id,
+            id,
+        }
+    }
+}
+impl Bounded for Tris3D {
+    fn aabb(&self) -> AABB {
+        AABB::with_bounds(point_to_point3(self.min), point_to_point3(self.max))
+    }
+}
+impl BHShape for Tris3D {
+    fn set_bh_node_index(&mut self, index: usize) {
+        self.node_index = index;
+    }
+
+    fn bh_node_index(&self) -> usize {
+        self.node_index
+    }
+}
+impl PartialEq for Tris3D {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+pub type UDIMs = HashMap<u32, Vec<Tris3D>>;
+
+/// Groups triangles by every UDIM tile their tiling UVs overlap.
+/// Also returns the number of triangles reaching outside of 1001-1100.
+pub fn group_udims(tris: &[Tris3D]) -> (UDIMs, usize) {
+    let mut udims_tris = UDIMs::new();
+    let mut outside = 0;
+    for t in tris {
+        let mut is_outside = false;
+        for (u, v) in uv_tiles(&t.v_tile) {
+            match tile_udim(u, v) {
+                Some(u_id) => {
+                    if let Entry::Vacant(entry) = udims_tris.entry(u_id) {
+                        entry.insert(vec![t.to_owned()]);
+                    } else {
+                        udims_tris.get_mut(&u_id).unwrap().push(t.to_owned());
+                    }
+                }
+                None => is_outside = true,
+            }
+        }
+        if is_outside {
+            outside += 1;
+        }
+    }
+    (udims_tris, outside)
+}
+
+/// Triangles grouped by UDIM tile, plus the full set and its BVH used for occlusion.
+pub struct Mesh {
+    pub udims: UDIMs,
+    pub tris: Vec<Tris3D>,
+    pub bvh: BVH,
+    pub tris_outside_udims: usize,
+}
+impl Mesh {
+    pub fn new(mut tris: Vec<Tris3D>) -> Self {
+        let (udims, tris_outside_udims) = group_udims(&tris);
+        let bvh = BVH::build(&mut tris);
+        Mesh {
+            udims,
+            tris,
+            bvh,
+            tris_outside_udims,
+        }
+    }
+    pub fn load(
+        path_data: &str,
+        uv_set: &UvChannel,
+        uv_tile_set: Option<&UvChannel>,
+    ) -> Result<Self> {
+        Ok(Mesh::new(load_meshes(path_data, uv_set, uv_tile_set)?))
+    }
+}
+
+// Signed doubled area of 2D triangle, positive for counter-clockwise.
+fn cross_2d(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+/// Splits a planar polygon into triangles by ear clipping, keeping its winding.
+/// Concave polygons are supported. Returns indices into `points`.
+pub fn triangulate(points: &[Point<f32>]) -> Vec<[usize; 3]> {
+    let n = points.len();
+    if n < 3 {
+        return vec![];
+    }
+    if n == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    //Project to the plane most perpendicular to Newell normal
+    let mut normal = [0.0f32; 3];
+    for i in 0..n {
+        let a = points[i];
+        let b = points[(i + 1) % n];
+        normal[0] += (a.y - b.y) * (a.z + b.z);
+        normal[1] += (a.z - b.z) * (a.x + b.x);
+        normal[2] += (a.x - b.x) * (a.y + b.y);
+    }
+    let [nx, ny, nz] = normal.map(f32::abs);
+    let flat: Vec<[f32; 2]> = points
+        .iter()
+        .map(|p| match (nx >= ny && nx >= nz, ny >= nz) {
+            (true, _) => [p.y, p.z],
+            (false, true) => [p.z, p.x],
+            (false, false) => [p.x, p.y],
+        })
+        .collect();
+    let area: f32 = (0..n)
+        .map(|i| cross_2d([0.0, 0.0], flat[i], flat[(i + 1) % n]))
+        .sum();
+    let winding = if area < 0.0 { -1.0 } else { 1.0 };
+
+    let mut indices: Vec<usize> = (0..n).collect();
+    let mut tris = Vec::with_capacity(n - 2);
+    while indices.len() > 3 {
+        let m = indices.len();
+        let ear = (0..m).find(|&i| {
+            let [p, c, next] = [indices[(i + m - 1) % m], indices[i], indices[(i + 1) % m]];
+            if cross_2d(flat[p], flat[c], flat[next]) * winding <= 0.0 {
+                return false;
+            }
+            !indices.iter().any(|&o| {
+                o != p
+                    && o != c
+                    && o != next
+                    && cross_2d(flat[p], flat[c], flat[o]) * winding >= 0.0
+                    && cross_2d(flat[c], flat[next], flat[o]) * winding >= 0.0
+                    && cross_2d(flat[next], flat[p], flat[o]) * winding >= 0.0
+            })
+        });
+        match ear {
+            Some(i) => {
+                tris.push([indices[(i + m - 1) % m], indices[i], indices[(i + 1) % m]]);
+                indices.remove(i);
+            }
+            //Self-intersecting or degenerate rest, fall back to fan
+            None => break,
+        }
+    }
+    for i in 1..indices.len() - 1 {
+        tris.push([indices[0], indices[i], indices[i + 1]]);
+    }
+    tris
+}
+
+/// Mesh files looked up in the data directory, in order of preference.
+pub const MESH_FILES: [&str; 3] = ["mesh.obj", "mesh.glb", "mesh.gltf"];
+
+pub fn load_meshes(
+    path_data: &str,
+    uv_set: &UvChannel,
+    uv_tile_set: Option<&UvChannel>,
+) -> Result<Vec<Tris3D>> {
+    let dir = Path::new(path_data);
+    let path = MESH_FILES
+        .iter()
+        .map(|f| dir.join(f))
+        .find(|p| p.is_file())
+        .unwrap_or_else(|| dir.join(MESH_FILES[0]));
+    load_mesh_file(&path, uv_set, uv_tile_set)
+}
+
+/// Loads `.obj`, `.gltf` or `.glb` by extension. OBJ only has UV set 0.
+/// Projection goes to `uv_set`, UDIM tiles are taken from `uv_tile_set` if given.
+pub fn load_mesh_file(
+    path: &Path,
+    uv_set: &UvChannel,
+    uv_tile_set: Option<&UvChannel>,
+) -> Result<Vec<Tris3D>> {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    let tris = match ext.as_deref() {
+        Some("gltf") | Some("glb") => load_gltf(path, uv_set, uv_tile_set)?,
+        _ if uv_set.index() != Some(0) || uv_tile_set.is_some_and(|t| t.index() != Some(0)) => {
+            return Err(EyekError::invalid_argument(
+                "uv_set",
+                format!("{} has a single UV set 0", path.display()),
+            ))
+        }
+        _ => load_obj(path)?,
+    };
+    if tris.is_empty() {
+        return Err(EyekError::Mesh {
+            path: path.to_path_buf(),
+            source: format!("no triangles with UV set {}", uv_set).into(),
+        });
+    }
+    Ok(tris)
+}
+
+pub fn load_obj(path: &Path) -> Result<Vec<Tris3D>> {
+    let path = path.to_path_buf();
+    let data = match obj::Obj::load(&path) {
+        Ok(obj) => obj.data,
+        Err(e) => {
+            return Err(EyekError::Mesh {
+                path,
+                source: e.into(),
+            })
+        }
+    };
+    let mut all_tris = Vec::<Tris3D>::new();
+    let mut tris_id: usize = 0;
+    for obj in data.objects {
+        for group in obj.groups {
+            for poly in group.polys {
+                let mut vs_pos = Vec::<Point<f32>>::new();
+                let mut vs_uv = Vec::<Point<f32>>::new();
+                for vert in poly.0 {
+                    let x = data.position[vert.0][0];
+                    let y = data.position[vert.0][1];
+                    let z = data.position[vert.0][2];
+                    let uv = match vert.1 {
+                        Some(i) => match data.texture.get(i) {
+                            Some(uv) => uv,
+                            _ => continue,
+                        },
+                        _ => continue,
+                    };
+                    vs_pos.push(Point { x, y, z });
+                    vs_uv.push(Point {
+                        x: uv[0],
+                        y: uv[1],
+                        z: 0.0,
+                    });
+                }
+
+                for [a, b, c] in triangulate(&vs_pos) {
+                    let tris = Tris3D::new(
+                        Triangle {
+                            a: vs_pos[a],
+                            b: vs_pos[b],
+                            c: vs_pos[c],
+                        },
+                        Triangle {
+                            a: vs_uv[a],
+                            b: vs_uv[b],
+                            c: vs_uv[c],
+                        },
+                        tris_id,
+                    );
+                    if tris.v_3d.is_collinear() {
+                        continue;
+                    }
+                    all_tris.push(tris);
+                    tris_id += 1;
+                }
+            }
+        }
+    }
+    Ok(all_tris)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f32, y: f32) -> Point<f32> {
+        Point { x, y, z: 0.0 }
+    }
+
+    fn flat_area(points: &[Point<f32>], [a, b, c]: [usize; 3]) -> f32 {
+        let flat = |i: usize| [points[i].x, points[i].y];
+        cross_2d(flat(a), flat(b), flat(c)) / 2.0
+    }
+
+    fn tris(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> Triangle<f32> {
+        Triangle {
+            a: point(a[0], a[1]),
+            b: point(b[0], b[1]),
+            c: point(c[0], c[1]),
+        }
+    }
+
+    #[test]
+    fn tile_udim_range() {
+        assert_eq!(tile_udim(0, 0), Some(1001));
+        assert_eq!(tile_udim(9, 0), Some(1010));
+        assert_eq!(tile_udim(0, 1), Some(1011));
+        assert_eq!(tile_udim(9, 9), Some(1100));
+        assert_eq!(tile_udim(10, 0), None);
+        assert_eq!(tile_udim(-1, 0), None);
+        assert_eq!(tile_udim(0, -1), None);
+        for udim in UDIM_FIRST..=UDIM_LAST {
+            let (u, v) = udim_tile(udim);
+            assert_eq!(tile_udim(u, v), Some(udim));
+        }
+    }
+
+    #[test]
+    fn uv_tiles_boundary() {
+        //Edge on u = 1 and corner on v = 1 only touch the neighbours
+        let edge = tris([0.5, 0.0], [1.0, 0.0], [1.0, 1.0]);
+        assert_eq!(uv_tiles(&edge), vec![(0, 0)]);
+        let corner = tris([0.0, 0.0], [1.0, 0.5], [1.0, 1.0]);
+        assert_eq!(uv_tiles(&corner), vec![(0, 0)]);
+        //Whole tile 1002 exactly
+        let tile = tris([1.0, 0.0], [2.0, 0.0], [2.0, 1.0]);
+        assert_eq!(uv_tiles(&tile), vec![(1, 0)]);
+    }
+
+    #[test]
+    fn uv_tiles_span() {
+        //Diagonal edge cuts the corner of (1, 0) but misses (1, 1)
+        let span = tris([0.5, 0.5], [1.2, 0.5], [0.5, 1.5]);
+        assert_eq!(uv_tiles(&span), vec![(0, 0), (1, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn uv_tiles_negative() {
+        let negative = tris([-0.5, 0.25], [0.5, 0.25], [0.0, 0.75]);
+        let tiles = uv_tiles(&negative);
+        assert_eq!(tiles, vec![(-1, 0), (0, 0)]);
+        let udims: Vec<_> = tiles.iter().map(|&(u, v)| tile_udim(u, v)).collect();
+        assert_eq!(udims, vec![None, Some(1001)]);
+        assert_eq!(uv_udim(-0.25, 0.5), None);
+        assert_eq!(uv_udim(0.25, 0.5), Some(1001));
+    }
+
+    #[test]
+    fn triangulate_concave() {
+        //L shape, the reflex vertex is at (1, 1)
+        let points = [
+            point(0.0, 0.0),
+            point(2.0, 0.0),
+            point(2.0, 1.0),
+            point(1.0, 1.0),
+            point(1.0, 2.0),
+            point(0.0, 2.0),
+        ];
+        let tris = triangulate(&points);
+        assert_eq!(tris.len(), 4);
+        for &tri in &tris {
+            assert!(
+                flat_area(&points, tri) > 0.0,
+                "{:?} is flipped or empty",
+                tri
+            );
+        }
+        let area: f32 = tris.iter().map(|&tri| flat_area(&points, tri)).sum();
+        assert!((area - 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn triangulate_keeps_winding() {
+        let points = [
+            point(0.0, 0.0),
+            point(0.0, 2.0),
+            point(1.0, 1.0),
+            point(2.0, 2.0),
+            point(2.0, 0.0),
+        ];
+        let tris = triangulate(&points);
+        assert_eq!(tris.len(), 3);
+        let area: f32 = tris.iter().map(|&tri| flat_area(&points, tri)).sum();
+        assert!((area + 3.0).abs() < 1e-6);
+        assert!(tris.iter().all(|&tri| flat_area(&points, tri) < 0.0));
+    }
+
+    #[test]
+    fn triangulate_small() {
+        assert!(triangulate(&[point(0.0, 0.0), point(1.0, 0.0)]).is_empty());
+        let tri = [point(0.0, 0.0), point(1.0, 0.0), point(0.0, 1.0)];
+        assert_eq!(triangulate(&tri), vec![[0, 1, 2]]);
+    }
+}
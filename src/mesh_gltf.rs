@@ -0,0 +1,162 @@
+use crate::error::{EyekError, Result};
+use crate::mesh::{point3_to_point, Tris3D, UvChannel};
+use bvh::nalgebra::{Matrix4, Point3};
+use gltf::accessor::{DataType, Dimensions, Iter};
+use gltf::mesh::{Mode, Semantic};
+use gltf::{buffer, Node, Primitive};
+use std::path::Path;
+use triangle::{Point, Triangle};
+
+fn mesh_error(path: &Path, source: gltf::Error) -> EyekError {
+    EyekError::Mesh {
+        path: path.to_path_buf(),
+        source: source.into(),
+    }
+}
+
+/// Loads triangles of every mesh in the default scene of a `.gltf` or `.glb` file.
+/// Node transforms are applied. UV channels are `TEXCOORD_<n>` or custom float VEC2 attributes.
+pub fn load_gltf(
+    path: &Path,
+    uv_set: &UvChannel,
+    uv_tile_set: Option<&UvChannel>,
+) -> Result<Vec<Tris3D>> {
+    let gltf = gltf::Gltf::open(path).map_err(|e| mesh_error(path, e))?;
+    let buffers = gltf::import_buffers(&gltf.document, path.parent(), gltf.blob.clone())
+        .map_err(|e| mesh_error(path, e))?;
+
+    let mut all_tris = Vec::<Tris3D>::new();
+    let roots: Vec<Node> = match gltf.document.default_scene() {
+        Some(scene) => scene.nodes().collect(),
+        None => match gltf.document.scenes().next() {
+            Some(scene) => scene.nodes().collect(),
+            None => vec![],
+        },
+    };
+    for node in roots {
+        load_node(
+            &node,
+            Matrix4::identity(),
+            &buffers,
+            [uv_set, uv_tile_set.unwrap_or(uv_set)],
+            &mut all_tris,
+        );
+    }
+    Ok(all_tris)
+}
+
+fn load_node(
+    node: &Node,
+    parent: Matrix4<f32>,
+    buffers: &[buffer::Data],
+    uv_sets: [&UvChannel; 2],
+    all_tris: &mut Vec<Tris3D>,
+) {
+    let transform = parent * Matrix4::from(node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        //Mirrored nodes flip the winding
+        let mirrored = transform.fixed_slice::<3, 3>(0, 0).determinant() < 0.0;
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                continue;
+            }
+            let reader = primitive.reader(|b| buffers.get(b.index()).map(|d| &d.0[..]));
+            let positions: Vec<Point<f32>> = match reader.read_positions() {
+                Some(iter) => iter
+                    .map(|p| {
+                        point3_to_point(transform.transform_point(&Point3::new(p[0], p[1], p[2])))
+                    })
+                    .collect(),
+                None => continue,
+            };
+            let uvs = match read_uvs(&primitive, buffers, uv_sets[0]) {
+                Some(uvs) => uvs,
+                None => continue,
+            };
+            let tile_uvs = match uv_sets[0] == uv_sets[1] {
+                true => uvs.clone(),
+                false => match read_uvs(&primitive, buffers, uv_sets[1]) {
+                    Some(uvs) => uvs,
+                    None => continue,
+                },
+            };
+            let indices: Vec<usize> = match reader.read_indices() {
+                Some(iter) => iter.into_u32().map(|i| i as usize).collect(),
+                None => (0..positions.len()).collect(),
+            };
+            for tri in indices.chunks_exact(3) {
+                let [a, b, c] = match mirrored {
+                    false => [tri[0], tri[1], tri[2]],
+                    true => [tri[0], tri[2], tri[1]],
+                };
+                if [a, b, c]
+                    .iter()
+                    .any(|&i| i >= positions.len() || i >= uvs.len() || i >= tile_uvs.len())
+                {
+                    continue;
+                }
+                let mut tris = Tris3D::new(
+                    Triangle {
+                        a: positions[a],
+                        b: positions[b],
+                        c: positions[c],
+                    },
+                    Triangle {
+                        a: uvs[a],
+                        b: uvs[b],
+                        c: uvs[c],
+                    },
+                    all_tris.len(),
+                );
+                tris.v_tile = Triangle {
+                    a: tile_uvs[a],
+                    b: tile_uvs[b],
+                    c: tile_uvs[c],
+                };
+                if tris.v_3d.is_collinear() {
+                    continue;
+                }
+                all_tris.push(tris);
+            }
+        }
+    }
+    for child in node.children() {
+        load_node(&child, transform, buffers, uv_sets, all_tris);
+    }
+}
+
+// glTF UV origin is top left, flipped to match OBJ.
+fn read_uvs(
+    primitive: &Primitive,
+    buffers: &[buffer::Data],
+    channel: &UvChannel,
+) -> Option<Vec<Point<f32>>> {
+    let get_buffer = |b: buffer::Buffer| buffers.get(b.index()).map(|d| &d.0[..]);
+    let uvs: Vec<[f32; 2]> = match channel.index() {
+        Some(set) => primitive
+            .reader(get_buffer)
+            .read_tex_coords(set)?
+            .into_f32()
+            .collect(),
+        None => {
+            let name = match channel {
+                UvChannel::Name(name) => name.trim_start_matches('_').to_string(),
+                UvChannel::Index(_) => return None,
+            };
+            let accessor = primitive.get(&Semantic::Extras(name))?;
+            if accessor.data_type() != DataType::F32 || accessor.dimensions() != Dimensions::Vec2 {
+                return None;
+            }
+            Iter::<[f32; 2]>::new(accessor, get_buffer)?.collect()
+        }
+    };
+    Some(
+        uvs.into_iter()
+            .map(|uv| Point {
+                x: uv[0],
+                y: 1.0 - uv[1],
+                z: 0.0,
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,170 @@
+use crate::camera::CameraRaw;
+use crate::error::{EyekError, Result};
+use crate::mesh::udim_tile;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Tokens replaced in the output path template.
+pub const TOKENS: [&str; 8] = [
+    "<UDIM>",
+    "<UVTILE>",
+    "<UVTILE0>",
+    "<U>",
+    "<V>",
+    "<U0>",
+    "<V0>",
+    "<camera>",
+];
+
+/// How UDIM tiles are named when `--out` has no tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TileNaming {
+    /// `out.png` for a single tile, `out.1001.png` otherwise.
+    #[default]
+    Auto,
+    /// Mari: `out.1001.png`.
+    Mari,
+    /// Mudbox and ZBrush: `out_u1_v1.png`.
+    Mudbox,
+    /// 0-based: `out_u0_v0.png`.
+    UvTile,
+}
+impl TileNaming {
+    pub const NAMES: [&'static str; 4] = ["auto", "mari", "mudbox", "uvtile"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "auto" => Some(TileNaming::Auto),
+            "mari" | "udim" => Some(TileNaming::Mari),
+            "mudbox" | "zbrush" => Some(TileNaming::Mudbox),
+            "uvtile" => Some(TileNaming::UvTile),
+            _ => None,
+        }
+    }
+
+    fn template(self, out: &str, udims_num: usize) -> String {
+        match self {
+            TileNaming::Auto if udims_num == 1 => out.to_string(),
+            TileNaming::Auto | TileNaming::Mari => format!("{}.<UDIM>", out),
+            TileNaming::Mudbox => format!("{}_<UVTILE>", out),
+            TileNaming::UvTile => format!("{}_<UVTILE0>", out),
+        }
+    }
+}
+
+pub fn is_template(out: &str) -> bool {
+    TOKENS.iter().any(|t| out.contains(t))
+}
+
+fn has_tile_token(out: &str) -> bool {
+    TOKENS.iter().any(|t| *t != "<camera>" && out.contains(t))
+}
+
+/// Whether the output template asks for a texture per camera instead of a blended one.
+pub fn is_per_camera(out: &str) -> bool {
+    out.contains("<camera>")
+}
+
+/// A template without a tile token can't name several tiles.
+pub fn check_template(out: &str, udims_num: usize) -> Result<()> {
+    match is_template(out) && udims_num > 1 && !has_tile_token(out) {
+        true => Err(EyekError::invalid_argument(
+            "out",
+            format!(
+                "{} UDIM tiles would overwrite each other, add <UDIM> or another tile token to '{}'",
+                udims_num, out
+            ),
+        )),
+        false => Ok(()),
+    }
+}
+
+/// Output file of a tile. `out` is either a template with tokens or a path
+/// without extension named by `naming`. `.png` is added when there is no extension.
+pub fn texture_path(
+    out: &str,
+    naming: TileNaming,
+    udim: u32,
+    udims_num: usize,
+    camera: Option<&str>,
+) -> Result<String> {
+    check_template(out, udims_num)?;
+    let template = match is_template(out) {
+        true => out.to_string(),
+        false => naming.template(out, udims_num),
+    };
+    let (u, v) = udim_tile(udim);
+    let path = template
+        .replace("<UDIM>", &udim.to_string())
+        .replace("<UVTILE>", &format!("u{}_v{}", u + 1, v + 1))
+        .replace("<UVTILE0>", &format!("u{}_v{}", u, v))
+        .replace("<U>", &(u + 1).to_string())
+        .replace("<V>", &(v + 1).to_string())
+        .replace("<U0>", &u.to_string())
+        .replace("<V0>", &v.to_string())
+        .replace("<camera>", camera.unwrap_or("all"));
+    let has_extension = match Path::new(&template).extension() {
+        Some(ext) => is_template(out) && !ext.to_string_lossy().contains('<'),
+        None => false,
+    };
+    Ok(match has_extension {
+        true => path,
+        false => format!("{}.png", path),
+    })
+}
+
+/// Names of cameras by id for `<camera>`.
+/// Names shared by several cameras get the camera id appended.
+pub fn camera_names(cameras: &[CameraRaw]) -> HashMap<usize, String> {
+    cameras
+        .iter()
+        .map(|cam| {
+            let name = match cameras.iter().filter(|c| c.name == cam.name).count() {
+                1 => cam.name.clone(),
+                _ => format!("{}_{}", cam.name, cam.id),
+            };
+            (cam.id, name)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(out: &str, naming: TileNaming, udim: u32, udims_num: usize) -> String {
+        texture_path(out, naming, udim, udims_num, None).unwrap()
+    }
+
+    #[test]
+    fn texture_path_naming() {
+        assert_eq!(path("out", TileNaming::Auto, 1001, 1), "out.png");
+        assert_eq!(path("out", TileNaming::Auto, 1012, 2), "out.1012.png");
+        assert_eq!(path("out", TileNaming::Mari, 1001, 1), "out.1001.png");
+        assert_eq!(path("out", TileNaming::Mudbox, 1012, 2), "out_u2_v2.png");
+        assert_eq!(path("out", TileNaming::UvTile, 1012, 2), "out_u1_v1.png");
+    }
+
+    #[test]
+    fn texture_path_template() {
+        let naming = TileNaming::Mudbox;
+        assert_eq!(path("tex/<UDIM>.jpg", naming, 1023, 3), "tex/1023.jpg");
+        assert_eq!(path("tex_<U>_<V>", naming, 1023, 3), "tex_3_3.png");
+        assert_eq!(path("tex_<U0>_<V0>.tif", naming, 1023, 3), "tex_2_2.tif");
+        assert_eq!(path("tex.<UDIM>", naming, 1001, 1), "tex.1001.png");
+        let camera = texture_path("tex_<camera>.<UDIM>", naming, 1001, 2, Some("cam"));
+        assert_eq!(camera.unwrap(), "tex_cam.1001.png");
+        let all = texture_path("tex_<camera>.png", naming, 1001, 1, None);
+        assert_eq!(all.unwrap(), "tex_all.png");
+    }
+
+    #[test]
+    fn texture_path_missing_tile_token() {
+        let result = texture_path("tex_<camera>.png", TileNaming::Auto, 1001, 2, Some("cam"));
+        assert!(matches!(result, Err(EyekError::InvalidArgument { .. })));
+        assert!(check_template("tex_<camera>.png", 1).is_ok());
+        assert!(check_template("tex", 2).is_ok());
+    }
+}
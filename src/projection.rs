@@ -0,0 +1,834 @@
+use crate::blending::{average, Color};
+use crate::camera::{CameraRaw, CameraType, Distortion, SensorFit};
+use crate::error::{EyekError, Result};
+use crate::mesh::{point3_to_point, point_to_point3, Tris3D};
+use crate::sampling::{sample, Filter, Mipmap, Pyramid};
+use crate::Properties;
+use bvh::bvh::BVH;
+use bvh::nalgebra::geometry::{Isometry3, Orthographic3, Perspective3, Translation3};
+use bvh::nalgebra::{Point3, Vector3};
+use bvh::ray::Ray;
+use image::{DynamicImage, GenericImageView, GrayImage, RgbaImage};
+use std::f32::consts::{FRAC_PI_2, PI};
+use triangle::{Point, Triangle};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FisheyeLens {
+    Equidistant,
+    Equisolid,
+    Stereographic,
+}
+impl FisheyeLens {
+    // Image radius of a ray at angle theta from the optical axis, for unit focal length.
+    fn radius(self, theta: f32) -> f32 {
+        match self {
+            FisheyeLens::Equidistant => theta,
+            FisheyeLens::Equisolid => 2.0 * (theta / 2.0).sin(),
+            FisheyeLens::Stereographic => 2.0 * (theta / 2.0).tan(),
+        }
+    }
+
+    fn angle(self, radius: f32) -> f32 {
+        match self {
+            FisheyeLens::Equidistant => radius,
+            FisheyeLens::Equisolid => 2.0 * (radius / 2.0).min(1.0).asin(),
+            FisheyeLens::Stereographic => 2.0 * (radius / 2.0).atan(),
+        }
+    }
+}
+
+pub enum Projection {
+    Persp(Perspective3<f32>),
+    Ortho(Orthographic3<f32>),
+    /// Longitude maps to x, latitude to y, distance to z.
+    Equirect {
+        znear: f32,
+        zfar: f32,
+    },
+    /// Angle from the optical axis maps to the radius, distance to z.
+    /// `radius` is the lens radius at the image edge, `scale` turns it into NDC x and y.
+    Fisheye {
+        lens: FisheyeLens,
+        radius: f32,
+        scale: [f32; 2],
+        znear: f32,
+        zfar: f32,
+    },
+}
+impl Projection {
+    // Non-linear projections bend straight edges, so projected corners
+    // don't bound the face in the image.
+    fn is_linear(&self) -> bool {
+        matches!(self, Projection::Persp(_) | Projection::Ortho(_))
+    }
+
+    fn clip_range(&self) -> [f32; 2] {
+        match self {
+            Projection::Persp(pr) => [pr.znear(), pr.zfar()],
+            Projection::Ortho(pr) => [pr.znear(), pr.zfar()],
+            Projection::Equirect { znear, zfar } => [*znear, *zfar],
+            Projection::Fisheye { znear, zfar, .. } => [*znear, *zfar],
+        }
+    }
+}
+
+// Point in camera space seen at the NDC position, on the far plane.
+fn unproject_far(projection: &Projection, x: f32, y: f32) -> Point3<f32> {
+    match projection {
+        Projection::Persp(pr) => pr.unproject_point(&Point3::new(x, y, 1.0)),
+        Projection::Ortho(pr) => pr.unproject_point(&Point3::new(x, y, 1.0)),
+        Projection::Equirect { zfar, .. } => {
+            let (lon, lat) = (x * PI, y * FRAC_PI_2);
+            Point3::new(
+                zfar * lat.cos() * lon.sin(),
+                zfar * lat.sin(),
+                -zfar * lat.cos() * lon.cos(),
+            )
+        }
+        Projection::Fisheye {
+            lens,
+            radius,
+            scale,
+            zfar,
+            ..
+        } => {
+            let [rx, ry] = [x / scale[0], y / scale[1]];
+            let theta = lens.angle(rx.hypot(ry) * radius);
+            let phi = ry.atan2(rx);
+            Point3::new(
+                zfar * theta.sin() * phi.cos(),
+                zfar * theta.sin() * phi.sin(),
+                -zfar * theta.cos(),
+            )
+        }
+    }
+}
+
+struct CameraView {
+    iso: Isometry3<f32>,
+    projection: Projection,
+    // Shift of the image center in NDC, from lens shift and principal point.
+    offset: [f32; 2],
+    distortion: Option<Distortion>,
+    img: DynamicImage,
+    mask: Option<GrayImage>,
+    mips: Option<Pyramid>,
+}
+
+// Alpha of images with alpha, luminance of the others.
+fn load_mask(mask_path: &str) -> Result<GrayImage> {
+    let mask = image::open(mask_path).map_err(|e| EyekError::CameraImage {
+        path: mask_path.into(),
+        source: e,
+    })?;
+    Ok(match mask.color().has_alpha() {
+        true => {
+            let rgba = mask.to_rgba8();
+            GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+                image::Luma([rgba.get_pixel(x, y)[3]])
+            })
+        }
+        false => mask.to_luma8(),
+    })
+}
+
+// Mask value at a source pixel, the mask may have another resolution.
+fn mask_value(mask: &GrayImage, x: u32, y: u32, width: u32, height: u32) -> u8 {
+    let mx = (x as u64 * mask.width() as u64 / width as u64) as u32;
+    let my = (y as u64 * mask.height() as u64 / height as u64) as u32;
+    mask.get_pixel(mx.min(mask.width() - 1), my.min(mask.height() - 1))[0]
+}
+
+fn project_point_to_cam(pt: Point<f32>, view: &CameraView) -> Point<f32> {
+    let iso = &view.iso;
+    let pt = match &view.projection {
+        Projection::Persp(pr) => {
+            point3_to_point(pr.project_point(&iso.inverse_transform_point(&point_to_point3(pt))))
+        }
+        Projection::Ortho(pr) => {
+            point3_to_point(pr.project_point(&iso.inverse_transform_point(&point_to_point3(pt))))
+        }
+        Projection::Equirect { .. } => {
+            let p = iso.inverse_transform_point(&point_to_point3(pt));
+            let lon = p.x.atan2(-p.z);
+            let lat = p.y.atan2((p.x * p.x + p.z * p.z).sqrt());
+            Point {
+                x: lon / PI,
+                y: lat / FRAC_PI_2,
+                z: p.coords.norm(),
+            }
+        }
+        Projection::Fisheye {
+            lens,
+            radius,
+            scale,
+            ..
+        } => {
+            let p = iso.inverse_transform_point(&point_to_point3(pt));
+            let theta = p.x.hypot(p.y).atan2(-p.z);
+            let phi = p.y.atan2(p.x);
+            let r = lens.radius(theta) / radius;
+            Point {
+                x: r * phi.cos() * scale[0],
+                y: r * phi.sin() * scale[1],
+                z: p.coords.norm(),
+            }
+        }
+    };
+    Point {
+        x: pt.x + view.offset[0],
+        y: pt.y + view.offset[1],
+        z: pt.z,
+    }
+}
+
+// Moves an ideal NDC point to where the lens distortion shows it in the source photo.
+fn distort_ndc(p_cam: Point<f32>, view: &CameraView) -> [f32; 2] {
+    let (distortion, pr) = match (&view.distortion, &view.projection) {
+        (Some(distortion), Projection::Persp(pr)) => (distortion, pr),
+        _ => return [p_cam.x, p_cam.y],
+    };
+    //NDC to focal-normalized coordinates, y down
+    let m = pr.as_matrix();
+    let [f_x, f_y] = [m[(0, 0)], m[(1, 1)]];
+    let [off_x, off_y] = view.offset;
+    let [x, y] = distortion.distort((p_cam.x - off_x) / f_x, -(p_cam.y - off_y) / f_y);
+    [x * f_x + off_x, -y * f_y + off_y]
+}
+
+// Alpha factor fading a sample out near the image border and at grazing view angles.
+fn feather(
+    face: &Tris3D,
+    p_bary: &Point<f32>,
+    view: &CameraView,
+    px: [f32; 2],
+    properties: &Properties,
+) -> f32 {
+    let mut factor = 1.0;
+    //Panoramas have no borders
+    if properties.feather > 0 && !matches!(view.projection, Projection::Equirect { .. }) {
+        let (w, h) = view.img.dimensions();
+        let border = px[0].min(w as f32 - px[0]).min(px[1]).min(h as f32 - px[1]);
+        factor *= (border / properties.feather as f32).clamp(0.0, 1.0);
+    }
+    if properties.feather_angle > 0.0 {
+        if let Some(n) = face.v_3d.normal() {
+            let to_cam = match view.projection {
+                Projection::Ortho(_) => view.iso.rotation * Vector3::new(0.0, 0.0, 1.0),
+                _ => {
+                    let p = face.v_3d.barycentric_to_cartesian(p_bary);
+                    view.iso.translation.vector - Vector3::new(p.x, p.y, p.z)
+                }
+            };
+            let cos = Vector3::new(n.x, n.y, n.z).dot(&to_cam.normalize()).abs();
+            let to_grazing = 90.0 - cos.min(1.0).acos().to_degrees();
+            factor *= (to_grazing / properties.feather_angle).clamp(0.0, 1.0);
+        }
+    }
+    factor
+}
+
+// NDC offset of the image center for lens shift and principal point.
+// Shift is in fractions of the fitted image dimension, or of the larger one without a fit.
+fn center_offset(
+    camera_raw: &CameraRaw,
+    fit: Option<SensorFit>,
+    width: f32,
+    height: f32,
+) -> [f32; 2] {
+    let aspect = width * camera_raw.pixel_aspect / height;
+    //Fitted dimension in image heights
+    let fitted = match fit {
+        Some(SensorFit::Horizontal) => aspect,
+        Some(SensorFit::Vertical) => 1.0,
+        _ => aspect.max(1.0),
+    };
+    let [shift_x, shift_y] = camera_raw.shift;
+    let [pp_x, pp_y] = match camera_raw.principal_point {
+        Some([cx, cy]) => [(2.0 * cx - width) / width, (height - 2.0 * cy) / height],
+        None => [0.0, 0.0],
+    };
+    [
+        pp_x - 2.0 * shift_x * fitted / aspect,
+        pp_y - 2.0 * shift_y * fitted,
+    ]
+}
+
+pub fn cast_pixels_rays(
+    all_tris: &[Tris3D],
+    camera_raw: CameraRaw,
+    faces: &[Tris3D],
+    bvh: &BVH,
+    texture: &mut RgbaImage,
+    tile: Option<(i32, i32)>,
+    properties: &Properties,
+) -> Result<()> {
+    let img = image::open(&camera_raw.image_path).map_err(|e| EyekError::CameraImage {
+        path: (&camera_raw.image_path).into(),
+        source: e,
+    })?;
+    let width = img.dimensions().0 as usize;
+    let height = img.dimensions().1 as usize;
+    // if properties.upscale > 0 {
+    //     width *= 2_usize.pow(properties.upscale as u32);
+    //     height *= 2_usize.pow(properties.upscale as u32);
+
+    //     img.resize(width as u32, height as u32, FilterType::CatmullRom);
+    // }
+
+    let ratio = width as f32 * camera_raw.pixel_aspect / height as f32;
+    let fit = match camera_raw.sensor_fit {
+        Some(SensorFit::Auto) if ratio >= 1.0 => Some(SensorFit::Horizontal),
+        Some(SensorFit::Auto) => Some(SensorFit::Vertical),
+        fit => fit,
+    };
+    let fov_y = match fit {
+        Some(SensorFit::Vertical) => camera_raw.fov_x,
+        _ => 2.0 * ((camera_raw.fov_x / 2.0).tan() / ratio).atan(),
+    };
+    let limit_near = camera_raw.limit_near;
+    let limit_far = camera_raw.limit_far;
+    let [cam_x, cam_y, cam_z] = camera_raw.pos;
+    let rot = camera_raw.rot;
+    let [sc_x, sc_y] = match fit {
+        None => [camera_raw.scale[0], camera_raw.scale[1]],
+        Some(SensorFit::Vertical) => [camera_raw.scale[1] * ratio, camera_raw.scale[1]],
+        Some(_) => [camera_raw.scale[0], camera_raw.scale[0] / ratio],
+    };
+    let cam_tr = Translation3::new(cam_x, cam_y, cam_z);
+    let iso = Isometry3::from_parts(cam_tr, rot);
+    let projection = match camera_raw.camera_type {
+        CameraType::Perspective => {
+            Projection::Persp(Perspective3::new(ratio, fov_y, limit_near, limit_far))
+        }
+        CameraType::Equirectangular => Projection::Equirect {
+            znear: limit_near,
+            zfar: limit_far,
+        },
+        CameraType::FisheyeEquidistant
+        | CameraType::FisheyeEquisolid
+        | CameraType::FisheyeStereographic => {
+            let lens = match camera_raw.camera_type {
+                CameraType::FisheyeEquidistant => FisheyeLens::Equidistant,
+                CameraType::FisheyeEquisolid => FisheyeLens::Equisolid,
+                _ => FisheyeLens::Stereographic,
+            };
+            Projection::Fisheye {
+                lens,
+                radius: lens.radius(camera_raw.fov_x / 2.0),
+                scale: match fit {
+                    Some(SensorFit::Vertical) => [1.0 / ratio, 1.0],
+                    _ => [1.0, ratio],
+                },
+                znear: limit_near,
+                zfar: limit_far,
+            }
+        }
+        CameraType::Orthographic => Projection::Ortho(Orthographic3::new(
+            -sc_x / 2.0,
+            sc_x / 2.0,
+            -sc_y / 2.0,
+            sc_y / 2.0,
+            limit_near,
+            limit_far,
+        )),
+    };
+    let offset = center_offset(&camera_raw, fit, width as f32, height as f32);
+    let mask = match &camera_raw.mask_path {
+        Some(mask_path) => Some(load_mask(mask_path)?),
+        None => None,
+    };
+    let mips = match properties.mipmap {
+        Mipmap::Off => None,
+        _ => Some(Pyramid::new(&img)),
+    };
+    let view = CameraView {
+        iso,
+        projection,
+        offset,
+        distortion: camera_raw.distortion,
+        img,
+        mask,
+        mips,
+    };
+
+    let faces_visible = match properties.backface_culling {
+        true => faces
+            .iter()
+            .filter(|f| !backface(f, &view.iso, &view.projection))
+            .collect::<Vec<&Tris3D>>(),
+        false => faces.iter().collect::<Vec<&Tris3D>>(),
+    };
+    for face in faces_visible {
+        face_img_to_uv(all_tris, bvh, face, &view, texture, tile, properties);
+    }
+    Ok(())
+}
+
+fn is_face_closest(
+    face: &Tris3D,
+    faces_visible: Vec<&Tris3D>,
+    ray: Ray,
+    near: f32,
+    far: f32,
+) -> bool {
+    if faces_visible.is_empty() {
+        return false;
+    }
+    let ray_orig = point3_to_point(ray.origin);
+    let ray_dir = Point {
+        x: ray.direction.x,
+        y: ray.direction.y,
+        z: ray.direction.z,
+    };
+    let mut closest: Vec<(Option<f32>, &Tris3D)> = faces_visible
+        .into_iter()
+        .map(|f| (f.v_3d.ray_intersection(&ray_orig, &ray_dir), f))
+        .filter(|h| h.0.is_some() && !h.0.unwrap().is_nan())
+        .collect();
+    if closest.is_empty() {
+        return false;
+    }
+    if closest.len() > 1 {
+        closest.sort_by(|a, b| {
+            a.0.partial_cmp(&b.0).unwrap()
+            //.expect(&format!("{:?} {:?}", &a.0, &b.0))
+        });
+    }
+    let dist_to_first = closest[0].0.unwrap();
+    if dist_to_first >= near && dist_to_first <= far {
+        return closest[0].1 == face;
+    }
+    false
+    // let dist_to_first = closest[0].0.unwrap();
+    // let epsilon = (dist_to_first - near) / ((far - near).max(f32::MIN));
+    // let closest_faces = closest
+    //     .iter()
+    //     .filter(|f| {
+    //         ((f.0.unwrap() - dist_to_first).abs() <= epsilon)
+    //             && f.0.unwrap() >= near
+    //             && f.0.unwrap() <= far
+    //     })
+    //     .map(|f| f.1)
+    //     .collect::<Vec<&Tris3D>>();
+    // closest_faces.contains(&face)
+}
+
+fn repeat_bounds(x: isize, dim: f32) -> u32 {
+    let rep = x as f32 % dim;
+    if rep < 0.0 {
+        (dim + rep) as u32
+    } else {
+        rep as u32
+    }
+}
+
+fn _conform_uv(w: f32) -> f32 {
+    match w {
+        w if w > 1.0 => w % 1.0,
+        _ => w,
+    }
+}
+fn backface(face: &Tris3D, iso: &Isometry3<f32>, projection: &Projection) -> bool {
+    let normal = face.v_3d.normal();
+    match normal {
+        None => true,
+        Some(n) => {
+            let ray_origin_pt =
+                Point3::new(iso.translation.x, iso.translation.y, iso.translation.z);
+            //Wide lenses look many ways, so the direction to the face is used
+            let ray_target_pt = match projection {
+                Projection::Equirect { .. } | Projection::Fisheye { .. } => {
+                    point_to_point3(face.v_3d.centroid())
+                }
+                _ => iso.transform_point(&unproject_far(projection, 0.0, 0.0)),
+            };
+            let cam_vec = Vector3::new(
+                ray_target_pt.x - ray_origin_pt.x,
+                ray_target_pt.y - ray_origin_pt.y,
+                ray_target_pt.z - ray_origin_pt.z,
+            );
+            let normal_vec = Vector3::new(n.x, n.y, n.z);
+            let dot = cam_vec.dot(&normal_vec);
+            if dot > 0.0 {
+                return true;
+            }
+            false
+        }
+    }
+}
+
+// Point of the face in camera space at barycentric coordinates of its UVs.
+// Only orthographic projection keeps barycentrics, perspective divides by depth,
+// so other points are projected from the 3D face.
+fn face_point_to_cam(
+    face: &Tris3D,
+    face_cam: &Triangle<f32>,
+    p_bary: &Point<f32>,
+    view: &CameraView,
+) -> Point<f32> {
+    match view.projection {
+        Projection::Ortho(_) => face_cam.barycentric_to_cartesian(p_bary),
+        _ => project_point_to_cam(face.v_3d.barycentric_to_cartesian(p_bary), view),
+    }
+}
+
+// Source pixel position of a UV point, from the image top left corner.
+fn uv_to_px(
+    face: &Tris3D,
+    face_cam: &Triangle<f32>,
+    p_uv: Point<f32>,
+    view: &CameraView,
+    [width, height]: [f32; 2],
+) -> [f32; 2] {
+    let p_bary = face.v_uv.cartesian_to_barycentric(&p_uv);
+    let [img_x, img_y] = distort_ndc(face_point_to_cam(face, face_cam, &p_bary, view), view);
+    [width * (img_x + 1.0) / 2.0, height * (1.0 - img_y) / 2.0]
+}
+
+// Source pixels covered by one sample: image offsets of its UV neighbours `texel` apart.
+fn footprint(
+    face: &Tris3D,
+    face_cam: &Triangle<f32>,
+    view: &CameraView,
+    p_uv: Point<f32>,
+    px: [f32; 2],
+    texel: [f32; 2],
+) -> [[f32; 2]; 2] {
+    let size = [view.img.width() as f32, view.img.height() as f32];
+    [[texel[0], 0.0], [0.0, texel[1]]].map(|[du, dv]| {
+        let p_next = Point {
+            x: p_uv.x + du,
+            y: p_uv.y + dv,
+            z: 0.0,
+        };
+        let [next_x, next_y] = uv_to_px(face, face_cam, p_next, view, size);
+        let mut d = [next_x - px[0], next_y - px[1]];
+        //Equirectangular images wrap around
+        if matches!(view.projection, Projection::Equirect { .. }) && d[0].abs() > size[0] / 2.0 {
+            d[0] -= size[0] * d[0].signum();
+        }
+        d
+    })
+}
+
+fn face_img_to_uv(
+    all_tris: &[Tris3D],
+    bvh: &BVH,
+    face: &Tris3D,
+    view: &CameraView,
+    texture: &mut RgbaImage,
+    tile: Option<(i32, i32)>,
+    properties: &Properties,
+) {
+    let iso = &view.iso;
+    let projection = &view.projection;
+    let img = &view.img;
+    let clip_uv = properties.clip_uv;
+    let uv_width = texture.dimensions().0 as f32;
+    let uv_height = texture.dimensions().1 as f32;
+    let tris_bounds = face.v_uv.aabb();
+    let (origin_u, origin_v) = tile.unwrap_or((0, 0));
+    let mut uv_min_u = ((tris_bounds[0].x - origin_u as f32) * uv_width).floor() as isize;
+    let mut uv_min_v = ((tris_bounds[0].y - origin_v as f32) * uv_height).floor() as isize;
+    let mut uv_max_u = ((tris_bounds[1].x - origin_u as f32) * uv_width).ceil() as isize;
+    let mut uv_max_v = ((tris_bounds[1].y - origin_v as f32) * uv_height).ceil() as isize;
+    //Parts of the face in other tiles are painted with those tiles
+    if tile.is_some() {
+        uv_min_u = uv_min_u.max(0);
+        uv_min_v = uv_min_v.max(0);
+        uv_max_u = uv_max_u.min(uv_width as isize - 1);
+        uv_max_v = uv_max_v.min(uv_height as isize - 1);
+    }
+
+    let cam_width = img.dimensions().0 as f32;
+    let cam_height = img.dimensions().1 as f32;
+
+    let face_cam = Triangle {
+        a: project_point_to_cam(face.v_3d.a, view),
+        b: project_point_to_cam(face.v_3d.b, view),
+        c: project_point_to_cam(face.v_3d.c, view),
+    };
+    if projection.is_linear() && face_cam.is_collinear() {
+        return;
+    }
+    for v in uv_min_v..=uv_max_v {
+        for u in uv_min_u..=uv_max_u {
+            let wrap = tile.is_none() && !clip_uv;
+            let uv_u = match wrap {
+                true => repeat_bounds(u, uv_width),
+                false => u as u32,
+            };
+            let uv_v = match wrap {
+                true => repeat_bounds(v, uv_height),
+                false => v as u32,
+            };
+            let ray_disp = properties
+                .supersampling
+                .offsets(properties.supersampling_grid, u, v);
+            let mut colors_to_mix = Vec::<Color>::new();
+            for d in ray_disp.iter() {
+                let p_uv = Point {
+                    x: origin_u as f32 + (u as f32 + d[0]) / uv_width,
+                    y: origin_v as f32 + (v as f32 + d[1]) / uv_height,
+                    z: 0.0,
+                };
+                if face.v_uv.has_point(p_uv) {
+                    let p_bary = face.v_uv.cartesian_to_barycentric(&p_uv);
+                    let p_cam = face_point_to_cam(face, &face_cam, &p_bary, view);
+
+                    let [img_x, img_y] = distort_ndc(p_cam, view);
+
+                    //Projected points behind the camera land in the image too
+                    let in_view = match projection {
+                        Projection::Ortho(_) => face_cam.has_point(p_cam),
+                        Projection::Persp(_) => (-1.0..=1.0).contains(&p_cam.z),
+                        //Panoramas keep the distance in z
+                        Projection::Equirect { .. } | Projection::Fisheye { .. } => {
+                            let [znear, zfar] = projection.clip_range();
+                            (znear..=zfar).contains(&p_cam.z)
+                        }
+                    };
+                    if in_view && img_x >= -1.0 && img_y >= -1.0 && img_x <= 1.0 && img_y <= 1.0 {
+                        let px = [
+                            cam_width * (img_x + 1.0) / 2.0,
+                            cam_height * (1.0 - img_y) / 2.0,
+                        ];
+                        let cam_x = (cam_width * (img_x + 1.0) / 2.0) as u32;
+                        let cam_y = (cam_height * (img_y + 1.0) / 2.0) as u32;
+                        if cam_x < cam_width as u32
+                            && cam_y < cam_height as u32
+                            && uv_u < uv_width as u32
+                            && uv_v < uv_height as u32
+                        {
+                            let face_is_visible = match properties.occlude {
+                                true => {
+                                    let ray_x = p_cam.x - view.offset[0];
+                                    let ray_y = p_cam.y - view.offset[1];
+                                    let ray_origin_pt = match projection {
+                                        Projection::Ortho(pr) => iso.transform_point(
+                                            &pr.unproject_point(&Point3::new(ray_x, ray_y, -1.0)),
+                                        ),
+                                        _ => Point3::new(
+                                            iso.translation.x,
+                                            iso.translation.y,
+                                            iso.translation.z,
+                                        ),
+                                    };
+                                    let ray_target_pt = iso
+                                        .transform_point(&unproject_far(projection, ray_x, ray_y));
+
+                                    let ray = Ray::new(
+                                        ray_origin_pt,
+                                        Vector3::new(
+                                            ray_target_pt.x - ray_origin_pt.x,
+                                            ray_target_pt.y - ray_origin_pt.y,
+                                            ray_target_pt.z - ray_origin_pt.z,
+                                        ),
+                                    );
+
+                                    let [znear, zfar] = projection.clip_range();
+
+                                    is_face_closest(
+                                        face,
+                                        bvh.traverse(&ray, all_tris),
+                                        ray,
+                                        znear,
+                                        zfar,
+                                    )
+                                }
+                                false => true,
+                            };
+
+                            if face_is_visible {
+                                let src_y = cam_height as u32 - cam_y - 1;
+                                let wrap_x = matches!(projection, Projection::Equirect { .. });
+                                let mut source_color = match (&view.mips, properties.filter) {
+                                    (Some(mips), filter) => {
+                                        let spacing = properties
+                                            .supersampling
+                                            .spacing(properties.supersampling_grid);
+                                        let texel = [spacing / uv_width, spacing / uv_height];
+                                        let footprint =
+                                            footprint(face, &face_cam, view, p_uv, px, texel);
+                                        mips.sample(
+                                            px[0],
+                                            px[1],
+                                            footprint,
+                                            filter,
+                                            properties.mipmap,
+                                            wrap_x,
+                                        )
+                                    }
+                                    (None, Filter::Nearest) => img.get_pixel(cam_x, src_y),
+                                    (None, filter) => sample(img, px[0], px[1], filter, wrap_x),
+                                };
+                                if let Some(mask) = &view.mask {
+                                    let (w, h) = img.dimensions();
+                                    let m = mask_value(mask, cam_x, src_y, w, h) as u32;
+                                    source_color[3] = (source_color[3] as u32 * m / 255) as u8;
+                                }
+                                if properties.feather > 0 || properties.feather_angle > 0.0 {
+                                    let f = feather(face, &p_bary, view, px, properties);
+                                    source_color[3] = (source_color[3] as f32 * f).round() as u8;
+                                }
+                                colors_to_mix.push(source_color);
+                            }
+                        }
+                    }
+                }
+            }
+            if !colors_to_mix.is_empty() {
+                texture.put_pixel(uv_u, uv_height as u32 - uv_v - 1, average(colors_to_mix));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bvh::nalgebra::UnitQuaternion;
+
+    const WIDTH: f32 = 400.0;
+    const HEIGHT: f32 = 200.0;
+
+    // Perspective camera at the origin looking down -Z.
+    fn camera() -> CameraRaw {
+        CameraRaw {
+            id: 0,
+            pos: [0.0, 0.0, 0.0],
+            rot: UnitQuaternion::identity(),
+            scale: [1.0, 1.0, 1.0],
+            camera_type: CameraType::Perspective,
+            fov_x: 1.0,
+            limit_near: 0.1,
+            limit_far: 100.0,
+            image_path: String::new(),
+            mask_path: None,
+            name: String::new(),
+            shift: [0.0, 0.0],
+            principal_point: None,
+            sensor_fit: None,
+            pixel_aspect: 1.0,
+            distortion: None,
+            weight: 1.0,
+            opacity: 1.0,
+            priority: 0,
+        }
+    }
+
+    fn view(camera: &CameraRaw) -> CameraView {
+        let ratio = WIDTH / HEIGHT;
+        let fov_y = 2.0 * ((camera.fov_x / 2.0).tan() / ratio).atan();
+        CameraView {
+            iso: Isometry3::identity(),
+            projection: Projection::Persp(Perspective3::new(ratio, fov_y, 0.1, 100.0)),
+            offset: center_offset(camera, camera.sensor_fit, WIDTH, HEIGHT),
+            distortion: camera.distortion,
+            img: DynamicImage::new_rgba8(WIDTH as u32, HEIGHT as u32),
+            mask: None,
+            mips: None,
+        }
+    }
+
+    // Source pixel the camera sees the point at.
+    fn pixel(camera: &CameraRaw, x: f32, y: f32, z: f32) -> [f32; 2] {
+        let view = view(camera);
+        let p_cam = project_point_to_cam(Point { x, y, z }, &view);
+        let [img_x, img_y] = distort_ndc(p_cam, &view);
+        [WIDTH * (img_x + 1.0) / 2.0, HEIGHT * (1.0 - img_y) / 2.0]
+    }
+
+    fn assert_px(a: [f32; 2], b: [f32; 2]) {
+        assert!(
+            (a[0] - b[0]).abs() < 1e-3 && (a[1] - b[1]).abs() < 1e-3,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn optical_axis_at_principal_point() {
+        assert_px(pixel(&camera(), 0.0, 0.0, -5.0), [200.0, 100.0]);
+        let cam = CameraRaw {
+            principal_point: Some([180.0, 120.0]),
+            ..camera()
+        };
+        assert_px(pixel(&cam, 0.0, 0.0, -5.0), [180.0, 120.0]);
+    }
+
+    #[test]
+    fn shift_by_fit_dimension() {
+        //Shifting the view right moves the scene left
+        let horizontal = CameraRaw {
+            shift: [0.5, 0.0],
+            sensor_fit: Some(SensorFit::Horizontal),
+            ..camera()
+        };
+        assert_px(pixel(&horizontal, 0.0, 0.0, -5.0), [0.0, 100.0]);
+        //A quarter of the width is half the height
+        let up = CameraRaw {
+            shift: [0.0, 0.25],
+            sensor_fit: Some(SensorFit::Horizontal),
+            ..camera()
+        };
+        assert_px(pixel(&up, 0.0, 0.0, -5.0), [200.0, 200.0]);
+        //Vertical fit measures both shifts in image heights
+        let vertical = CameraRaw {
+            shift: [0.5, 0.0],
+            sensor_fit: Some(SensorFit::Vertical),
+            ..camera()
+        };
+        assert_px(pixel(&vertical, 0.0, 0.0, -5.0), [100.0, 100.0]);
+        //Without a fit the larger dimension is used
+        let unfitted = CameraRaw {
+            shift: [0.0, 0.25],
+            ..camera()
+        };
+        assert_px(pixel(&unfitted, 0.0, 0.0, -5.0), [200.0, 200.0]);
+    }
+
+    #[test]
+    fn distortion_around_principal_point() {
+        let brown = |k1| Distortion::Brown {
+            k1,
+            k2: 0.0,
+            k3: 0.0,
+            p1: 0.0,
+            p2: 0.0,
+        };
+        let (zero, barrel) = (brown(0.0), brown(-0.2));
+        let ideal = CameraRaw {
+            principal_point: Some([180.0, 120.0]),
+            ..camera()
+        };
+        let off_axis = pixel(&ideal, 1.0, 0.5, -3.0);
+        for distortion in [zero, barrel] {
+            let cam = CameraRaw {
+                distortion: Some(distortion),
+                ..ideal.clone()
+            };
+            assert_px(pixel(&cam, 0.0, 0.0, -5.0), [180.0, 120.0]);
+        }
+        let zero_cam = CameraRaw {
+            distortion: Some(zero),
+            ..ideal.clone()
+        };
+        assert_px(pixel(&zero_cam, 1.0, 0.5, -3.0), off_axis);
+        //Barrel distortion pulls points towards the principal point
+        let barrel_cam = CameraRaw {
+            distortion: Some(barrel),
+            ..ideal
+        };
+        let [x, y] = pixel(&barrel_cam, 1.0, 0.5, -3.0);
+        let radius = |[x, y]: [f32; 2]| (x - 180.0).hypot(y - 120.0);
+        assert!(radius([x, y]) < radius(off_axis));
+        //Along the line through the principal point
+        assert!(
+            ((y - 120.0) / (x - 180.0) - (off_axis[1] - 120.0) / (off_axis[0] - 180.0)).abs()
+                < 1e-3
+        );
+    }
+}
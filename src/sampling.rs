@@ -0,0 +1,535 @@
+use crate::blending::Color;
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use serde_derive::Deserialize;
+use std::f32::consts::PI;
+
+/// Filter used to read source photos between their pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    #[default]
+    Nearest,
+    Bilinear,
+    /// Catmull-Rom spline.
+    Bicubic,
+    /// Lanczos with 3 lobes.
+    Lanczos,
+}
+impl Filter {
+    pub const NAMES: [&'static str; 4] = ["nearest", "bilinear", "bicubic", "lanczos"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "nearest" => Some(Filter::Nearest),
+            "bilinear" | "linear" => Some(Filter::Bilinear),
+            "bicubic" | "cubic" | "catmull-rom" => Some(Filter::Bicubic),
+            "lanczos" => Some(Filter::Lanczos),
+            _ => None,
+        }
+    }
+
+    fn radius(self) -> f32 {
+        match self {
+            Filter::Nearest => 0.5,
+            Filter::Bilinear => 1.0,
+            Filter::Bicubic => 2.0,
+            Filter::Lanczos => 3.0,
+        }
+    }
+
+    fn weight(self, t: f32) -> f32 {
+        let t = t.abs();
+        match self {
+            //The only tap may be half a pixel away, between two pixels
+            Filter::Nearest => match t <= 0.5 {
+                true => 1.0,
+                false => 0.0,
+            },
+            Filter::Bilinear => (1.0 - t).max(0.0),
+            Filter::Bicubic => match t {
+                t if t < 1.0 => 1.5 * t * t * t - 2.5 * t * t + 1.0,
+                t if t < 2.0 => -0.5 * t * t * t + 2.5 * t * t - 4.0 * t + 2.0,
+                _ => 0.0,
+            },
+            Filter::Lanczos => match t {
+                t if t < f32::EPSILON => 1.0,
+                t if t < 3.0 => 3.0 * (PI * t).sin() * (PI * t / 3.0).sin() / (PI * PI * t * t),
+                _ => 0.0,
+            },
+        }
+    }
+}
+
+/// Reads the image at `x`, `y` in pixels from its top left corner, pixel centers at .5.
+/// Taps outside the image repeat the border pixels, or wrap horizontally with `wrap_x`.
+/// Colors are filtered with premultiplied alpha so transparent pixels don't bleed in.
+pub fn sample<I>(img: &I, x: f32, y: f32, filter: Filter, wrap_x: bool) -> Color
+where
+    I: GenericImageView<Pixel = Color>,
+{
+    unpremultiply(sample_premultiplied(img, x, y, filter, wrap_x))
+}
+
+fn sample_premultiplied<I>(img: &I, x: f32, y: f32, filter: Filter, wrap_x: bool) -> [f32; 4]
+where
+    I: GenericImageView<Pixel = Color>,
+{
+    let (width, height) = img.dimensions();
+    let radius = filter.radius();
+    let (cx, cy) = (x - 0.5, y - 0.5);
+    let taps = |c: f32| (c - radius).floor() as i64 + 1..=(c + radius).floor() as i64;
+    let mut sum = [0.0_f32; 4];
+    let mut total = 0.0;
+    for ty in taps(cy) {
+        let wy = filter.weight(ty as f32 - cy);
+        if wy == 0.0 {
+            continue;
+        }
+        let py = ty.clamp(0, height as i64 - 1) as u32;
+        for tx in taps(cx) {
+            let w = wy * filter.weight(tx as f32 - cx);
+            if w == 0.0 {
+                continue;
+            }
+            let px = match wrap_x {
+                true => tx.rem_euclid(width as i64) as u32,
+                false => tx.clamp(0, width as i64 - 1) as u32,
+            };
+            let c = img.get_pixel(px, py);
+            let a = c[3] as f32;
+            sum[0] += c[0] as f32 * a * w;
+            sum[1] += c[1] as f32 * a * w;
+            sum[2] += c[2] as f32 * a * w;
+            sum[3] += a * w;
+            total += w;
+        }
+    }
+    match total == 0.0 {
+        true => [0.0; 4],
+        false => sum.map(|s| s / total),
+    }
+}
+
+fn unpremultiply(c: [f32; 4]) -> Color {
+    if c[3] <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    Rgba([
+        (c[0] / c[3]).round().clamp(0.0, 255.0) as u8,
+        (c[1] / c[3]).round().clamp(0.0, 255.0) as u8,
+        (c[2] / c[3]).round().clamp(0.0, 255.0) as u8,
+        c[3].round().clamp(0.0, 255.0) as u8,
+    ])
+}
+
+/// Mip lookup for texels covering many source pixels, as for distant or grazing shots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mipmap {
+    #[default]
+    Off,
+    /// One lookup between the two levels closest to the footprint size.
+    Trilinear,
+    /// Several trilinear lookups along the long axis of the footprint.
+    Anisotropic,
+}
+impl Mipmap {
+    pub const NAMES: [&'static str; 3] = ["off", "trilinear", "anisotropic"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "off" | "none" => Some(Mipmap::Off),
+            "trilinear" => Some(Mipmap::Trilinear),
+            "anisotropic" => Some(Mipmap::Anisotropic),
+            _ => None,
+        }
+    }
+}
+
+const MAX_ANISOTROPY: f32 = 16.0;
+
+/// Source photo with levels of half the size of the previous one, down to one pixel.
+pub struct Pyramid {
+    levels: Vec<RgbaImage>,
+}
+impl Pyramid {
+    pub fn new(img: &DynamicImage) -> Self {
+        let mut levels = vec![img.to_rgba8()];
+        loop {
+            let last = &levels[levels.len() - 1];
+            if last.width() == 1 && last.height() == 1 {
+                break;
+            }
+            let next = downsample(last);
+            levels.push(next);
+        }
+        Pyramid { levels }
+    }
+
+    /// Reads the pyramid at `x`, `y` in level 0 pixels, as in [`sample`].
+    /// `dx` and `dy` are the footprint sides in level 0 pixels.
+    pub fn sample(
+        &self,
+        x: f32,
+        y: f32,
+        [dx, dy]: [[f32; 2]; 2],
+        filter: Filter,
+        mipmap: Mipmap,
+        wrap_x: bool,
+    ) -> Color {
+        //Levels are read at least bilinearly, sharper filters are kept
+        let filter = match filter {
+            Filter::Nearest => Filter::Bilinear,
+            filter => filter,
+        };
+        let (len_x, len_y) = (dx[0].hypot(dx[1]), dy[0].hypot(dy[1]));
+        let (major, minor, axis) = match len_x >= len_y {
+            true => (len_x, len_y, dx),
+            false => (len_y, len_x, dy),
+        };
+        let c = match mipmap {
+            Mipmap::Off => self.sample_lod(x, y, 0.0, filter, wrap_x),
+            Mipmap::Trilinear => self.sample_lod(x, y, major.log2(), filter, wrap_x),
+            Mipmap::Anisotropic => {
+                let count = (major / minor.max(f32::EPSILON))
+                    .ceil()
+                    .clamp(1.0, MAX_ANISOTROPY);
+                let lod = (major / count).log2();
+                let mut sum = [0.0_f32; 4];
+                for i in 0..count as usize {
+                    let t = (i as f32 + 0.5) / count - 0.5;
+                    let c = self.sample_lod(x + axis[0] * t, y + axis[1] * t, lod, filter, wrap_x);
+                    sum.iter_mut().zip(c).for_each(|(s, c)| *s += c / count);
+                }
+                sum
+            }
+        };
+        unpremultiply(c)
+    }
+
+    fn sample_lod(&self, x: f32, y: f32, lod: f32, filter: Filter, wrap_x: bool) -> [f32; 4] {
+        let lod = match lod.is_nan() {
+            true => 0.0,
+            false => lod.clamp(0.0, (self.levels.len() - 1) as f32),
+        };
+        let level = lod.floor() as usize;
+        let t = lod - level as f32;
+        let a = self.sample_level(level, x, y, filter, wrap_x);
+        if t == 0.0 {
+            return a;
+        }
+        let b = self.sample_level(level + 1, x, y, filter, wrap_x);
+        [0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * t)
+    }
+
+    fn sample_level(&self, level: usize, x: f32, y: f32, filter: Filter, wrap_x: bool) -> [f32; 4] {
+        let img = &self.levels[level];
+        let base = &self.levels[0];
+        let sx = img.width() as f32 / base.width() as f32;
+        let sy = img.height() as f32 / base.height() as f32;
+        sample_premultiplied(img, x * sx, y * sy, filter, wrap_x)
+    }
+}
+
+// Box filter over 2×2 pixels with premultiplied alpha.
+fn downsample(img: &RgbaImage) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    RgbaImage::from_fn((width / 2).max(1), (height / 2).max(1), |x, y| {
+        let mut sum = [0.0_f32; 4];
+        for (sx, sy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let c = img.get_pixel((2 * x + sx).min(width - 1), (2 * y + sy).min(height - 1));
+            let a = c[3] as f32;
+            sum[0] += c[0] as f32 * a / 4.0;
+            sum[1] += c[1] as f32 * a / 4.0;
+            sum[2] += c[2] as f32 * a / 4.0;
+            sum[3] += a / 4.0;
+        }
+        unpremultiply(sum)
+    })
+}
+
+/// Where rays are cast inside every texel, results are averaged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Supersampling {
+    /// Four texel corners.
+    #[default]
+    Corners,
+    /// Texel center only, fastest.
+    Center,
+    /// One random point in every cell of an N×N grid.
+    Jittered,
+    /// N×N grid rotated so that no two samples share a row or column.
+    RotatedGrid,
+}
+impl Supersampling {
+    pub const NAMES: [&'static str; 4] = ["corners", "center", "jittered", "rotated_grid"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace('-', "_").as_str() {
+            "corners" => Some(Supersampling::Corners),
+            "center" | "centre" => Some(Supersampling::Center),
+            "jittered" | "stratified" => Some(Supersampling::Jittered),
+            "rotated_grid" | "rgss" => Some(Supersampling::RotatedGrid),
+            _ => None,
+        }
+    }
+
+    /// Distance between neighbouring samples in texels, the footprint of one sample.
+    pub fn spacing(self, grid: u32) -> f32 {
+        match self {
+            Supersampling::Corners | Supersampling::Center => 1.0,
+            Supersampling::Jittered | Supersampling::RotatedGrid => 1.0 / grid.max(1) as f32,
+        }
+    }
+
+    /// Sample offsets in 0..1 inside texel `u`, `v` for a `grid`×`grid` pattern.
+    /// Jitter is hashed from the texel so results are reproducible.
+    pub fn offsets(self, grid: u32, u: isize, v: isize) -> Vec<[f32; 2]> {
+        let n = grid.max(1);
+        let cells = (0..n * n).map(|i| (i, i % n, i / n));
+        match self {
+            Supersampling::Corners => vec![[0.0, 0.0], [0.99, 0.99], [0.0, 0.99], [0.99, 0.0]],
+            Supersampling::Center => vec![[0.5, 0.5]],
+            Supersampling::Jittered => cells
+                .map(|(i, x, y)| {
+                    let seed = hash([u as u32, v as u32, i]);
+                    let [jx, jy] = [seed & 0xffff, seed >> 16].map(|r| r as f32 / 65536.0);
+                    [(x as f32 + jx) / n as f32, (y as f32 + jy) / n as f32]
+                })
+                .collect(),
+            Supersampling::RotatedGrid => cells
+                .map(|(_, x, y)| {
+                    //Rotated by atan(1/N) and scaled to spread over N² rows and columns
+                    let [gx, gy] = [x, y].map(|c| (c as f32 + 0.5) / n as f32 - 0.5);
+                    [0.5 + gx - gy / n as f32, 0.5 + gy + gx / n as f32]
+                })
+                .collect(),
+        }
+    }
+}
+
+fn hash(values: [u32; 3]) -> u32 {
+    let mut h: u32 = 0x9e37_79b9;
+    for v in values {
+        h = (h ^ v).wrapping_mul(0x85eb_ca6b);
+        h ^= h >> 13;
+        h = h.wrapping_mul(0xc2b2_ae35);
+        h ^= h >> 16;
+    }
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [Filter; 4] = [
+        Filter::Nearest,
+        Filter::Bilinear,
+        Filter::Bicubic,
+        Filter::Lanczos,
+    ];
+
+    // Opaque image with a distinct color in every pixel.
+    fn gradient(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([(x * 40) as u8, (y * 50) as u8, ((x + y) * 20) as u8, 255])
+        })
+    }
+
+    #[test]
+    fn sample_pixel_centers() {
+        let img = gradient(6, 5);
+        for filter in FILTERS {
+            for (x, y, c) in img.enumerate_pixels() {
+                let s = sample(&img, x as f32 + 0.5, y as f32 + 0.5, filter, false);
+                assert_eq!(s, *c, "{:?} at {}, {}", filter, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn sample_borders() {
+        let img = gradient(6, 5);
+        let (left, right) = (*img.get_pixel(0, 2), *img.get_pixel(5, 2));
+        for filter in FILTERS {
+            //Beyond the filter radius only border pixels are read
+            assert_eq!(sample(&img, -4.0, 2.5, filter, false), left, "{:?}", filter);
+            assert_eq!(
+                sample(&img, 10.0, 2.5, filter, false),
+                right,
+                "{:?}",
+                filter
+            );
+            assert_eq!(sample(&img, 0.5, -4.0, filter, false), *img.get_pixel(0, 0));
+        }
+        //Wrapping reads the opposite side
+        assert_eq!(sample(&img, 6.5, 2.5, Filter::Bilinear, true), left);
+        assert_eq!(sample(&img, -0.5, 2.5, Filter::Bilinear, true), right);
+        let seam = sample(&img, 0.0, 2.5, Filter::Bilinear, true);
+        let between = [0, 1, 2].map(|i| ((left[i] as f32 + right[i] as f32) / 2.0).round() as u8);
+        assert_eq!(seam, Rgba([between[0], between[1], between[2], 255]));
+        assert_eq!(sample(&img, 0.0, 2.5, Filter::Bilinear, false), left);
+    }
+
+    #[test]
+    fn sample_transparent_neighbours() {
+        let img = RgbaImage::from_fn(4, 1, |x, _| match x < 2 {
+            true => Rgba([255, 200, 0, 255]),
+            false => Rgba([0, 0, 0, 0]),
+        });
+        for filter in [Filter::Bilinear, Filter::Bicubic, Filter::Lanczos] {
+            let c = sample(&img, 2.0, 0.5, filter, false);
+            assert_eq!([c[0], c[1], c[2]], [255, 200, 0], "{:?}", filter);
+            assert!(c[3] > 0 && c[3] < 255, "{:?} {:?}", filter, c);
+        }
+        assert_eq!(
+            sample(&img, 3.5, 0.5, Filter::Bilinear, false),
+            Rgba([0, 0, 0, 0])
+        );
+    }
+
+    const PATTERNS: [Supersampling; 4] = [
+        Supersampling::Corners,
+        Supersampling::Center,
+        Supersampling::Jittered,
+        Supersampling::RotatedGrid,
+    ];
+
+    #[test]
+    fn offsets_inside_texel() {
+        for pattern in PATTERNS {
+            for grid in 1..=16 {
+                for (u, v) in [(0, 0), (7, 3), (-2, 5), (1023, 1023)] {
+                    for [x, y] in pattern.offsets(grid, u, v) {
+                        assert!((0.0..1.0).contains(&x), "{:?} {} {}", pattern, grid, x);
+                        assert!((0.0..1.0).contains(&y), "{:?} {} {}", pattern, grid, y);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rotated_grid_rows_and_columns() {
+        for grid in 1..=16 {
+            let offsets = Supersampling::RotatedGrid.offsets(grid, 0, 0);
+            assert_eq!(offsets.len(), (grid * grid) as usize);
+            //Every sample in its own one of the N² rows and columns
+            let min_gap = 0.5 / (grid * grid) as f32;
+            for (i, a) in offsets.iter().enumerate() {
+                for b in &offsets[i + 1..] {
+                    assert!(
+                        (a[0] - b[0]).abs() > min_gap,
+                        "grid {} column {:?} {:?}",
+                        grid,
+                        a,
+                        b
+                    );
+                    assert!(
+                        (a[1] - b[1]).abs() > min_gap,
+                        "grid {} row {:?} {:?}",
+                        grid,
+                        a,
+                        b
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn jittered_per_texel() {
+        let jittered = Supersampling::Jittered;
+        assert_eq!(jittered.offsets(4, 12, 34), jittered.offsets(4, 12, 34));
+        assert_ne!(jittered.offsets(4, 12, 34), jittered.offsets(4, 13, 34));
+        assert_ne!(jittered.offsets(4, 12, 34), jittered.offsets(4, 12, 35));
+        //One sample in every cell of the grid
+        let n = 4;
+        let mut cells: Vec<_> = jittered
+            .offsets(n, 12, 34)
+            .iter()
+            .map(|[x, y]| ((x * n as f32) as u32, (y * n as f32) as u32))
+            .collect();
+        cells.sort();
+        cells.dedup();
+        assert_eq!(cells.len(), (n * n) as usize);
+    }
+
+    fn pyramid(img: RgbaImage) -> Pyramid {
+        Pyramid::new(&DynamicImage::ImageRgba8(img))
+    }
+
+    const WHITE: Color = Rgba([255, 255, 255, 255]);
+    const BLACK: Color = Rgba([0, 0, 0, 255]);
+    const GRAY: Color = Rgba([128, 128, 128, 255]);
+
+    #[test]
+    fn pyramid_levels() {
+        let mips = pyramid(gradient(16, 8));
+        let sizes: Vec<_> = mips.levels.iter().map(|l| l.dimensions()).collect();
+        assert_eq!(sizes, vec![(16, 8), (8, 4), (4, 2), (2, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn pyramid_pixel_footprint() {
+        let img = gradient(16, 16);
+        let mips = pyramid(img.clone());
+        let pixel = [[1.0, 0.0], [0.0, 1.0]];
+        for mipmap in [Mipmap::Off, Mipmap::Trilinear, Mipmap::Anisotropic] {
+            for (x, y) in [(0, 0), (5, 9), (15, 15)] {
+                let c = mips.sample(
+                    x as f32 + 0.5,
+                    y as f32 + 0.5,
+                    pixel,
+                    Filter::Nearest,
+                    mipmap,
+                    false,
+                );
+                assert_eq!(c, *img.get_pixel(x, y), "{:?} at {}, {}", mipmap, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn pyramid_large_footprint() {
+        let checker = RgbaImage::from_fn(16, 16, |x, y| match (x + y) % 2 {
+            0 => WHITE,
+            _ => BLACK,
+        });
+        let mips = pyramid(checker);
+        let pixel = [[1.0, 0.0], [0.0, 1.0]];
+        let large = [[16.0, 0.0], [0.0, 16.0]];
+        let sample =
+            |footprint, mipmap| mips.sample(4.5, 4.5, footprint, Filter::Bilinear, mipmap, false);
+        assert_eq!(sample(pixel, Mipmap::Trilinear), WHITE);
+        assert_eq!(sample(large, Mipmap::Trilinear), GRAY);
+        assert_eq!(sample(large, Mipmap::Anisotropic), GRAY);
+        //Without mipmaps the footprint is ignored
+        assert_eq!(sample(large, Mipmap::Off), WHITE);
+    }
+
+    #[test]
+    fn pyramid_anisotropic_footprint() {
+        //Columns don't change along the long side of the footprint
+        let stripes = RgbaImage::from_fn(16, 16, |x, _| match x % 2 {
+            0 => WHITE,
+            _ => BLACK,
+        });
+        let mips = pyramid(stripes);
+        let tall = [[1.0, 0.0], [0.0, 16.0]];
+        let sample = |mipmap| mips.sample(4.5, 8.0, tall, Filter::Bilinear, mipmap, false);
+        assert_eq!(sample(Mipmap::Anisotropic), WHITE);
+        assert_eq!(sample(Mipmap::Trilinear), GRAY);
+    }
+
+    #[test]
+    fn spacing_by_pattern() {
+        assert_eq!(Supersampling::Corners.spacing(4), 1.0);
+        assert_eq!(Supersampling::Center.spacing(4), 1.0);
+        assert_eq!(Supersampling::Jittered.spacing(4), 0.25);
+        assert_eq!(Supersampling::RotatedGrid.spacing(2), 0.5);
+        assert_eq!(Supersampling::Jittered.spacing(0), 1.0);
+    }
+}